@@ -0,0 +1,216 @@
+use crate::{
+    diff::{compare, Change},
+    style_id, styled, Database, QueryOptions, QueryResult, CONFIG, DATETIME_FORMAT, NOW,
+};
+use ansi_term::{Colour, Style};
+use chrono::{DateTime, FixedOffset};
+use clap::{Args, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct AgendaArgs {
+    /// Database file path
+    #[arg(long, global = true, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    #[command(subcommand)]
+    command: AgendaCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum AgendaCommands {
+    /// Plan to go to a seance
+    Add {
+        /// Seance ID
+        seance: u64,
+    },
+    /// Stop planning to go
+    Remove {
+        /// Agenda entry ID
+        id: u64,
+    },
+    /// List planned seances
+    List,
+    /// Report planned seances which were moved, changed or cancelled since the last check,
+    /// exiting with 1 if there are any
+    Check,
+}
+
+/// A planned seance, as it was when it was planned or last checked
+pub struct Entry {
+    pub id: u64,
+    pub film_id: u64,
+    pub film_name: String,
+    pub cinema_url_path: String,
+    pub cinema_name: String,
+    pub datetime: DateTime<FixedOffset>,
+    pub version: String,
+    pub url: Option<String>,
+    /// Reported as cancelled by a check
+    pub cancelled: bool,
+}
+
+impl Entry {
+    fn description(&self) -> String {
+        format!(
+            "{} ({}) {} at {}",
+            self.datetime.format(&DATETIME_FORMAT),
+            self.version,
+            self.film_name,
+            self.cinema_name
+        )
+    }
+
+    /// Whether a seance is the one this entry was planned for
+    fn is(&self, result: &QueryResult) -> bool {
+        result.film.id == self.film_id
+            && result.cinema.url_path == self.cinema_url_path
+            && result.seance.datetime == self.datetime
+            && result.seance.version == self.version
+    }
+
+    /// What became of this entry's seance since a scrape run which saw it, pairing the
+    /// film's seances in the cinema then and now the way `cip diff` does
+    fn change<'a>(
+        &self,
+        before: &'a [QueryResult],
+        after: &'a [QueryResult],
+    ) -> Option<Change<'a>> {
+        let seances = |results: &'a [QueryResult]| {
+            results
+                .iter()
+                .filter(|r| r.film.id == self.film_id && r.cinema.url_path == self.cinema_url_path)
+                .filter(|r| r.seance.datetime >= *NOW)
+                .collect::<Vec<_>>()
+        };
+        let before = seances(before);
+        if !before.iter().any(|r| self.is(r)) {
+            return None;
+        }
+        compare(&before, &seances(after))
+            .into_iter()
+            .find(|change| match change {
+                Change::Cancelled(b) | Change::Changed { before: b, .. } => self.is(b),
+                Change::Added(_) => false,
+            })
+    }
+}
+
+pub async fn agenda(args: AgendaArgs) {
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    match args.command {
+        AgendaCommands::Add { seance } => match conn.get_seance(seance).unwrap() {
+            Some(result) => {
+                conn.insert_agenda(&result).unwrap();
+            }
+            None => {
+                println!("Seance {} not found", style_id(seance));
+                std::process::exit(2);
+            }
+        },
+        AgendaCommands::Remove { id } => {
+            if conn.delete_agenda(id).unwrap() == 0 {
+                println!("Agenda entry {} not found", style_id(id));
+            }
+        }
+        AgendaCommands::List => {
+            for entry in conn.get_agenda().unwrap() {
+                if entry.cancelled {
+                    println!(
+                        "{} {}, cancelled",
+                        style_id(entry.id),
+                        styled(Style::new().strikethrough()).paint(entry.description())
+                    );
+                    continue;
+                }
+                let line = format!("{} {}", style_id(entry.id), entry.description());
+                if entry.datetime < *NOW {
+                    println!("{}", styled(Style::new().dimmed()).paint(line));
+                } else {
+                    println!("{line}");
+                }
+            }
+        }
+        AgendaCommands::Check => {
+            let results = conn.query_seances(QueryOptions::default()).unwrap();
+            // the last run is the current programme after a full scrape, the one before
+            // it is what the agenda was last checked against
+            let runs = conn
+                .get_runs()
+                .unwrap()
+                .iter()
+                .rev()
+                .take(2)
+                .map(|run| conn.get_run_seances(run.id).unwrap())
+                .collect::<Vec<_>>();
+            let mut found = false;
+            for entry in conn.get_agenda().unwrap() {
+                if entry.datetime < *NOW || entry.cancelled {
+                    continue;
+                }
+                let result = match results.iter().find(|r| entry.is(r)) {
+                    Some(result) => result,
+                    // seances missing from the archive too were reported already
+                    None => match runs
+                        .iter()
+                        .find_map(|seances| entry.change(seances, &results))
+                    {
+                        Some(Change::Changed { after, .. }) => after,
+                        Some(_) => {
+                            println!(
+                                "{} {} {}, cancelled",
+                                styled(Colour::Red.normal()).paint("-"),
+                                style_id(entry.id),
+                                styled(Style::new().strikethrough()).paint(entry.description())
+                            );
+                            conn.cancel_agenda(entry.id).unwrap();
+                            found = true;
+                            continue;
+                        }
+                        None => continue,
+                    },
+                };
+                let mut changes = Vec::new();
+                if result.seance.datetime != entry.datetime {
+                    changes.push(format!(
+                        "moved to {}",
+                        result.seance.datetime.format(&DATETIME_FORMAT)
+                    ));
+                }
+                if result.seance.version != entry.version {
+                    changes.push(format!("now {}", result.seance.version));
+                }
+                if result.seance.url != entry.url {
+                    changes.push("new booking link".to_string());
+                }
+                if changes.is_empty() {
+                    continue;
+                }
+                match conn.update_agenda(entry.id, result) {
+                    // the seance it became is already planned as another entry
+                    Err(rusqlite::Error::SqliteFailure(err, _))
+                        if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+                    {
+                        conn.delete_agenda(entry.id).unwrap();
+                        changes.push("already on the agenda, entry removed".to_string());
+                    }
+                    updated => {
+                        updated.unwrap();
+                    }
+                }
+                println!(
+                    "{} {} {}, {} (seance {})",
+                    styled(Colour::Yellow.normal()).paint("~"),
+                    style_id(entry.id),
+                    entry.description(),
+                    changes.join(", "),
+                    style_id(result.seance.id)
+                );
+                found = true;
+            }
+            if found {
+                std::process::exit(1);
+            }
+        }
+    }
+}
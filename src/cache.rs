@@ -0,0 +1,116 @@
+use crate::{fnv1a, PROJECT_DIRS};
+use reqwest::{
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A cached response, stored as JSON in the cache directory
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Seconds since the epoch
+    fetched_at: u64,
+    body: String,
+}
+
+/// Stores responses with their validators, to make conditional requests for them later.
+/// It's shared by every database, so whether a page changed since the programme in a
+/// database was scraped is up to that database.
+pub struct HttpCache {
+    /// None when the cache is disabled
+    dir: Option<PathBuf>,
+    /// Cached responses younger than this are used without asking the server
+    max_age: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+impl HttpCache {
+    pub fn new(enabled: bool, max_age_minutes: i64) -> Self {
+        Self {
+            dir: enabled.then(|| PROJECT_DIRS.cache_dir().join("http")),
+            max_age: max_age_minutes.max(0) as u64 * 60,
+        }
+    }
+
+    fn path(&self, url: &str) -> Option<PathBuf> {
+        let hash = fnv1a(url.as_bytes());
+        Some(self.dir.as_ref()?.join(format!("{hash:016x}.json")))
+    }
+
+    fn load(&self, url: &str) -> Option<Entry> {
+        let entry = std::fs::read_to_string(self.path(url)?).ok()?;
+        serde_json::from_str::<Entry>(&entry)
+            .ok()
+            .filter(|entry| entry.url == url)
+    }
+
+    fn store(&self, entry: &Entry) {
+        let Some(path) = self.path(&entry.url) else {
+            return;
+        };
+        // a cache which can't be written only costs a download next time
+        let _ = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|()| std::fs::write(&path, serde_json::to_string(entry).unwrap()));
+    }
+
+    /// Gets a page, from the cache if it's recent enough or the server says it hasn't
+    /// changed. Error statuses are errors, and never cached.
+    pub async fn get(&self, client: &Client, url: &str) -> reqwest::Result<String> {
+        let cached = self.load(url);
+        if let Some(entry) = &cached {
+            if now().saturating_sub(entry.fetched_at) < self.max_age {
+                return Ok(entry.body.clone());
+            }
+        }
+
+        let mut request = client.get(url);
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(mut entry) = cached {
+                entry.fetched_at = now();
+                self.store(&entry);
+                return Ok(entry.body);
+            }
+        }
+        let response = response.error_for_status()?;
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+        let body = response.text().await?;
+        self.store(&Entry {
+            url: url.to_string(),
+            etag,
+            last_modified,
+            fetched_at: now(),
+            body: body.clone(),
+        });
+        Ok(body)
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    parse_cinema_page, styled, Cinema, CinemaPage, Database, Film, CINEMAS_URL, FILMS_URL,
+};
+use ansi_term::{Colour, Style};
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+};
+
+/// Seance counts changing by more than this factor since the previous run are flagged
+const ANOMALY_FACTOR: usize = 2;
+/// Cinemas with fewer seances than this in the previous run aren't checked for anomalies
+const ANOMALY_MIN_SEANCES: usize = 10;
+
+async fn download_list<T: DeserializeOwned>(url: &str) -> Result<Vec<T>, String> {
+    let response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?;
+    let json = response.text().await.map_err(|err| err.to_string())?;
+    serde_json::from_str(&json).map_err(|err| format!("unexpected JSON: {err}"))
+}
+
+async fn download_page(
+    cinema: &Cinema,
+    film_ids: Arc<HashMap<String, u64>>,
+) -> Result<CinemaPage, String> {
+    let response = reqwest::get(cinema.url())
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?;
+    let html = response.text().await.map_err(|err| err.to_string())?;
+    let id = cinema.id;
+    Ok(
+        tokio::task::spawn_blocking(move || parse_cinema_page(&html, id, &film_ids))
+            .await
+            .unwrap(),
+    )
+}
+
+/// Why a cinema's seance count looks wrong, if it does
+fn anomaly(seances: usize, previous: Option<usize>) -> Option<String> {
+    if seances == 0 {
+        return Some("no sessions".to_string());
+    }
+    let previous = previous.filter(|previous| *previous >= ANOMALY_MIN_SEANCES)?;
+    if seances * ANOMALY_FACTOR < previous || seances > previous * ANOMALY_FACTOR {
+        Some(format!("{previous} sessions in the previous run"))
+    } else {
+        None
+    }
+}
+
+/// Downloads the film and cinema lists and every cinema page, and reports whether they
+/// still have the layout the scraper expects, exiting with 1 if they don't
+pub async fn check(db_path: &Path, concurrency: usize) {
+    let mut drift = false;
+    let flag = |text: String| styled(Colour::Red.bold()).paint(text).to_string();
+
+    let (films, cinemas) = futures::future::join(
+        download_list::<Film>(FILMS_URL.as_str()),
+        download_list::<Cinema>(CINEMAS_URL.as_str()),
+    )
+    .await;
+    let films = match films {
+        Ok(films) => {
+            println!("Film list:   {} films", films.len());
+            films
+        }
+        Err(err) => {
+            println!("Film list:   {}", flag(err));
+            drift = true;
+            Vec::new()
+        }
+    };
+    let mut cinemas = match cinemas {
+        Ok(cinemas) => {
+            println!("Cinema list: {} cinemas", cinemas.len());
+            cinemas
+        }
+        Err(err) => {
+            println!("Cinema list: {}", flag(err));
+            std::process::exit(1);
+        }
+    };
+    for (id, cinema) in cinemas.iter_mut().enumerate() {
+        cinema.id = id as u64 + 1;
+    }
+    println!();
+
+    let db = Database::open(db_path);
+    let conn = db.conn().unwrap();
+    let previous = match conn.get_runs().unwrap().last() {
+        Some(run) => conn.get_run_cinema_counts(run.id).unwrap(),
+        None => HashMap::new(),
+    };
+
+    let film_ids = Arc::new(
+        films
+            .iter()
+            .map(|film| (film.url_path.clone(), film.id))
+            .collect::<HashMap<_, _>>(),
+    );
+    let pages = futures::stream::iter(
+        cinemas
+            .iter()
+            .map(|cinema| download_page(cinema, film_ids.clone())),
+    )
+    .buffered(concurrency.max(1))
+    .collect::<Vec<_>>()
+    .await;
+
+    println!(
+        "{}",
+        styled(Style::new().bold()).paint(format!(
+            "{:<40} {:>6} {:>9} {:>9}",
+            "Cinema", "films", "sessions", "previous"
+        ))
+    );
+    let mut drifted = 0;
+    for (cinema, page) in cinemas.iter().zip(pages) {
+        let previous = previous.get(&cinema.url_path).copied();
+        let previous_column = previous.map_or("-".to_string(), |p| p.to_string());
+        let page = match page {
+            Ok(page) => page,
+            Err(err) => {
+                println!("{:<40} {}", cinema.description(), flag(err));
+                drifted += 1;
+                continue;
+            }
+        };
+        // as the writer deduplicates them
+        let seances = page
+            .seances
+            .iter()
+            .map(|s| (s.film_id, s.datetime, &s.version, &s.url))
+            .collect::<HashSet<_>>()
+            .len();
+        let mut notes = page
+            .problems
+            .iter()
+            .map(|(problem, count)| format!("skipped {problem} ({count}x)"))
+            .collect::<Vec<_>>();
+        notes.extend(anomaly(seances, previous));
+        println!(
+            "{:<40} {:>6} {:>9} {:>9}",
+            cinema.description(),
+            page.films,
+            seances,
+            previous_column
+        );
+        if !page.discovered.is_empty() {
+            println!(
+                "  {} films missing from the film list",
+                page.discovered.len()
+            );
+        }
+        if !notes.is_empty() {
+            drifted += 1;
+            for note in notes {
+                println!("  {}", flag(note));
+            }
+        }
+    }
+    println!();
+
+    if drifted > 0 {
+        println!(
+            "{}",
+            flag(format!(
+                "{drifted} of {} cinemas don't look right",
+                cinemas.len()
+            ))
+        );
+        drift = true;
+    } else {
+        println!("All {} cinemas look right", cinemas.len());
+    }
+    if drift {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,507 @@
+use crate::{
+    notify::{self, Event, Notification},
+    paris_now, paris_time, parse_time,
+    plan::parse_minutes,
+    try_parse_time, Database, CONFIG, DATETIME_FORMAT, PROJECT_DIRS,
+};
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime};
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    process::Output,
+    sync::Arc,
+    time::Instant,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    process::Command,
+    sync::{Mutex, Notify},
+};
+
+/// Scrapes still running after this long are killed and count as failed
+const SCRAPE_TIMEOUT_MINUTES: u64 = 30;
+/// Watchlist, agenda and programme checks still running after this long are killed
+const CHECK_TIMEOUT_MINUTES: u64 = 5;
+
+/// The `[daemon]` section of the config file
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Time between scrapes, e.g. 1h or 30m
+    interval: String,
+    /// Don't scrape before HH:MM
+    from: String,
+    /// Don't scrape after HH:MM
+    to: String,
+    /// Control socket path, defaults to daemon.sock in the runtime directory
+    socket: Option<PathBuf>,
+    /// Command run with the title and body of every notification, e.g. ["notify-send"]
+    notify_command: Vec<String>,
+    /// Only run the notify command for these events, defaults to all of them
+    notify_events: Vec<Event>,
+    webhooks: Vec<notify::Webhook>,
+    email: Option<notify::Email>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            interval: "1h".to_string(),
+            from: "08:00".to_string(),
+            to: "23:00".to_string(),
+            socket: None,
+            notify_command: Vec::new(),
+            notify_events: Vec::new(),
+            webhooks: Vec::new(),
+            email: None,
+        }
+    }
+}
+
+impl DaemonConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let invalid = |field: &str, err: String| format!("Invalid daemon.{field}: {err}");
+        parse_minutes(&self.interval).map_err(|err| invalid("interval", err))?;
+        try_parse_time(&self.from).map_err(|err| invalid("from", err))?;
+        try_parse_time(&self.to).map_err(|err| invalid("to", err))?;
+        Ok(())
+    }
+}
+
+fn default_socket() -> PathBuf {
+    CONFIG.daemon.socket.clone().unwrap_or_else(|| {
+        PROJECT_DIRS
+            .runtime_dir()
+            .unwrap_or(PROJECT_DIRS.cache_dir())
+            .join("daemon.sock")
+    })
+}
+
+#[derive(Args, Debug)]
+pub struct DaemonArgs {
+    /// Database file path
+    #[arg(long, global = true, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Control socket path
+    #[arg(long, global = true, default_value = default_socket().display().to_string())]
+    socket: PathBuf,
+    /// Time between scrapes, e.g. 1h or 30m
+    #[arg(long, value_parser = parse_minutes, default_value = CONFIG.daemon.interval.clone())]
+    interval: i64,
+    /// Don't scrape before HH:MM
+    #[arg(long, default_value = CONFIG.daemon.from.clone())]
+    from: String,
+    /// Don't scrape after HH:MM
+    #[arg(long, default_value = CONFIG.daemon.to.clone())]
+    to: String,
+    /// Talk to a running daemon instead of starting one
+    #[command(subcommand)]
+    command: Option<DaemonCommands>,
+}
+
+#[derive(Subcommand, Debug)]
+enum DaemonCommands {
+    /// Show when the daemon last scraped and when it will next
+    Status,
+    /// Scrape now instead of waiting for the next scheduled run
+    Run,
+    /// Send a test notification through the notify command, webhooks and email
+    Test {
+        /// Event the notification is about
+        #[arg(long, short = 'e', value_enum, default_value_t = Event::ScrapeCompleted)]
+        event: Event,
+    },
+}
+
+/// When scrapes happen: every `interval` between `from` and `to`
+struct Schedule {
+    interval: Duration,
+    from: NaiveTime,
+    to: NaiveTime,
+}
+
+impl Schedule {
+    /// The first time in the schedule at least an interval after the last scrape, and no
+    /// earlier than now
+    fn next_run(&self, last: Option<DateTime<FixedOffset>>) -> DateTime<FixedOffset> {
+        let now = now();
+        let due = last.map_or(now, |last| (last + self.interval).max(now));
+        let at = |date: chrono::NaiveDate| paris_time(date.and_time(self.from));
+        if due.time() < self.from {
+            at(due.date_naive())
+        } else if due.time() > self.to {
+            at(due.date_naive().succ_opt().unwrap())
+        } else {
+            due
+        }
+    }
+}
+
+struct Report {
+    started_at: DateTime<FixedOffset>,
+    took: std::time::Duration,
+    /// A summary of the run or why it failed
+    outcome: Result<String, String>,
+}
+
+#[derive(Default)]
+struct State {
+    running: bool,
+    next_run: Option<DateTime<FixedOffset>>,
+    last: Option<Report>,
+    runs: usize,
+    failures: usize,
+}
+
+struct Daemon {
+    db_path: PathBuf,
+    started_at: DateTime<FixedOffset>,
+    state: Mutex<State>,
+    /// Wakes the scheduler up to scrape right away
+    trigger: Notify,
+}
+
+/// The current time, as `NOW` is only right when the daemon starts
+fn now() -> DateTime<FixedOffset> {
+    paris_now()
+}
+
+fn log(message: impl AsRef<str>) {
+    println!(
+        "[{}] {}",
+        now().format("%Y-%m-%d %H:%M:%S"),
+        message.as_ref()
+    );
+}
+
+/// Logs a notification and sends it to every sink, logging the ones it failed to reach
+async fn notify(notification: Notification) {
+    log(format!(
+        "{}\n{}",
+        notification.title,
+        notification.body.trim_end()
+    ));
+    let config = &CONFIG.daemon;
+    if let Some((program, args)) = config.notify_command.split_first() {
+        if notify::wanted(&config.notify_events, &notification) {
+            let status = Command::new(program)
+                .args(args)
+                .arg(&notification.title)
+                .arg(&notification.body)
+                .kill_on_drop(true)
+                .status();
+            let timeout = std::time::Duration::from_secs(notify::TIMEOUT_SECONDS);
+            match tokio::time::timeout(timeout, status).await {
+                Ok(Ok(status)) if status.success() => {}
+                Ok(Ok(status)) => log(format!("Notify command failed: {status}")),
+                Ok(Err(err)) => log(format!("Could not run the notify command: {err}")),
+                Err(_) => log(format!(
+                    "Notify command timed out after {} seconds",
+                    notify::TIMEOUT_SECONDS
+                )),
+            }
+        }
+    }
+    for webhook in &config.webhooks {
+        if let Err(err) = webhook.send(&notification).await {
+            log(format!("Could not send a webhook: {err}"));
+        }
+    }
+    if let Some(email) = &config.email {
+        if let Err(err) = email.send(&notification).await {
+            log(format!("Could not send an email: {err}"));
+        }
+    }
+}
+
+/// Runs a cip subcommand in a child process, so that it can't take the daemon down when it
+/// panics or exits, killing it if it takes longer than `timeout_minutes`
+async fn cip(args: &[&str], db_path: &Path, timeout_minutes: u64) -> Result<Output, String> {
+    let output = Command::new(std::env::current_exe().map_err(|err| err.to_string())?)
+        .arg("--no-color")
+        .args(args)
+        .arg("--db-path")
+        .arg(db_path)
+        .kill_on_drop(true)
+        .output();
+    tokio::time::timeout(std::time::Duration::from_secs(timeout_minutes * 60), output)
+        .await
+        .map_err(|_| format!("timed out after {timeout_minutes} minutes"))?
+        .map_err(|err| err.to_string())
+}
+
+/// The last lines a failed command printed, to tell what went wrong
+fn failure(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let text = if stderr.trim().is_empty() {
+        stdout
+    } else {
+        stderr
+    };
+    let lines = text.trim().lines().collect::<Vec<_>>();
+    let tail = lines[lines.len().saturating_sub(5)..].join("\n");
+    format!("{}\n{tail}", output.status)
+}
+
+impl Daemon {
+    /// Scrapes, then checks for new seances on the watchlist and changes to the programme,
+    /// returning what to notify once the run is over
+    async fn run(&self) -> Result<(String, Vec<Notification>), String> {
+        let scrape = cip(&["scrape"], &self.db_path, SCRAPE_TIMEOUT_MINUTES).await?;
+        if !scrape.status.success() {
+            return Err(failure(&scrape));
+        }
+
+        let mut runs = {
+            let db = Database::open(&self.db_path);
+            let conn = db.conn().map_err(|err| err.to_string())?;
+            conn.get_runs().map_err(|err| err.to_string())?
+        };
+        let mut checks = vec![
+            (
+                &["watch", "check"][..],
+                Event::Watchlist,
+                "New seances on your watchlist",
+            ),
+            (
+                &["agenda", "check"][..],
+                Event::Agenda,
+                "A seance you planned changed",
+            ),
+        ];
+        // the first run has nothing to compare with
+        if runs.len() > 1 {
+            checks.push((
+                &["diff", "--exit-code"][..],
+                Event::Programme,
+                "The programme changed",
+            ));
+        }
+        // exiting with 1 means there's something to report
+        let mut notifications = Vec::new();
+        for (args, event, title) in checks {
+            match cip(args, &self.db_path, CHECK_TIMEOUT_MINUTES).await {
+                Ok(output) if output.status.code() == Some(1) => notifications.push(Notification {
+                    event,
+                    title: title.to_string(),
+                    body: String::from_utf8_lossy(&output.stdout).into_owned(),
+                }),
+                Ok(output) if output.status.success() => {}
+                Ok(output) => log(format!(
+                    "cip {} failed: {}",
+                    args.join(" "),
+                    failure(&output)
+                )),
+                Err(err) => log(format!("Could not run cip {}: {err}", args.join(" "))),
+            }
+        }
+
+        let summary = runs.pop().map_or("nothing archived".to_string(), |run| {
+            format!("run {} with {} seances", run.id, run.seances)
+        });
+        Ok((summary, notifications))
+    }
+
+    async fn schedule(self: Arc<Self>, schedule: Schedule) {
+        let mut last = {
+            let db = Database::open(&self.db_path);
+            let conn = db.conn().unwrap();
+            conn.get_runs().unwrap().pop().map(|run| run.started_at)
+        };
+        loop {
+            let next_run = schedule.next_run(last);
+            self.state.lock().await.next_run = Some(next_run);
+            let wait = (next_run - now()).to_std().unwrap_or_default();
+            tokio::select! {
+                () = tokio::time::sleep(wait) => {}
+                () = self.trigger.notified() => {}
+            }
+
+            let started_at = now();
+            let start = Instant::now();
+            {
+                let mut state = self.state.lock().await;
+                state.running = true;
+                state.next_run = None;
+            }
+            log("Scraping");
+            // a panic in the daemon's own code only fails this run
+            let (outcome, mut notifications) = match tokio::spawn({
+                let daemon = self.clone();
+                async move { daemon.run().await }
+            })
+            .await
+            {
+                Ok(Ok((summary, notifications))) => (Ok(summary), notifications),
+                Ok(Err(err)) => (Err(err), Vec::new()),
+                Err(err) => (Err(err.to_string()), Vec::new()),
+            };
+            notifications.push(match &outcome {
+                Ok(summary) => Notification {
+                    event: Event::ScrapeCompleted,
+                    title: "Scrape completed".to_string(),
+                    body: summary.clone(),
+                },
+                Err(err) => Notification {
+                    event: Event::ScrapeFailed,
+                    title: "Scrape failed".to_string(),
+                    body: err.clone(),
+                },
+            });
+
+            // the run is over before notifying, which may be slow
+            {
+                let mut state = self.state.lock().await;
+                state.running = false;
+                state.runs += 1;
+                if outcome.is_err() {
+                    state.failures += 1;
+                }
+                state.last = Some(Report {
+                    started_at,
+                    took: start.elapsed(),
+                    outcome,
+                });
+            }
+            last = Some(started_at);
+            for notification in notifications {
+                notify(notification).await;
+            }
+        }
+    }
+
+    async fn status(&self) -> String {
+        let state = self.state.lock().await;
+        let mut status = format!(
+            "Running since {} (pid {})\n",
+            self.started_at.format(&DATETIME_FORMAT),
+            std::process::id()
+        );
+        if state.running {
+            status += "Scraping now\n";
+        } else if let Some(next_run) = state.next_run {
+            status += &format!("Next scrape at {}\n", next_run.format(&DATETIME_FORMAT));
+        }
+        if let Some(report) = &state.last {
+            let outcome = match &report.outcome {
+                Ok(summary) => summary.clone(),
+                Err(err) => format!("failed: {}", err.lines().next().unwrap_or_default()),
+            };
+            status += &format!(
+                "Last scrape at {}, took {}s: {outcome}\n",
+                report.started_at.format(&DATETIME_FORMAT),
+                report.took.as_secs()
+            );
+        }
+        status += &format!("{} scrapes, {} failed\n", state.runs, state.failures);
+        status
+    }
+
+    async fn handle(&self, stream: UnixStream) -> std::io::Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut command = String::new();
+        BufReader::new(read).read_line(&mut command).await?;
+        let reply = match command.trim() {
+            "status" => self.status().await,
+            "run" => {
+                if self.state.lock().await.running {
+                    "A scrape is already running\n".to_string()
+                } else {
+                    self.trigger.notify_one();
+                    "Scrape started\n".to_string()
+                }
+            }
+            command => format!("Unknown command {command}\n"),
+        };
+        write.write_all(reply.as_bytes()).await
+    }
+}
+
+/// Sends a command to the daemon listening on the socket and prints its reply
+async fn send(socket: &Path, command: &str) {
+    let Ok(mut stream) = UnixStream::connect(socket).await else {
+        println!("No daemon is listening on {}", socket.display());
+        std::process::exit(1);
+    };
+    stream
+        .write_all(format!("{command}\n").as_bytes())
+        .await
+        .unwrap();
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).await.unwrap();
+    print!("{reply}");
+}
+
+pub async fn daemon(args: DaemonArgs) {
+    match args.command {
+        Some(DaemonCommands::Status) => return send(&args.socket, "status").await,
+        Some(DaemonCommands::Run) => return send(&args.socket, "run").await,
+        Some(DaemonCommands::Test { event }) => {
+            return notify(Notification {
+                event,
+                title: "Test notification".to_string(),
+                body: "Notifications from cip will look like this".to_string(),
+            })
+            .await
+        }
+        None => {}
+    }
+
+    let schedule = Schedule {
+        interval: Duration::minutes(args.interval.max(1)),
+        from: parse_time(&args.from),
+        to: parse_time(&args.to),
+    };
+    if schedule.from > schedule.to {
+        println!("--from should be before --to");
+        std::process::exit(2);
+    }
+
+    if UnixStream::connect(&args.socket).await.is_ok() {
+        println!("A daemon is already listening on {}", args.socket.display());
+        std::process::exit(1);
+    }
+    // left behind by a daemon which didn't shut down cleanly
+    let _ = std::fs::remove_file(&args.socket);
+    std::fs::create_dir_all(args.socket.parent().unwrap()).unwrap();
+    let listener = UnixListener::bind(&args.socket).unwrap_or_else(|err| {
+        println!("Could not listen on {}: {err}", args.socket.display());
+        std::process::exit(1);
+    });
+    log(format!(
+        "Scraping every {}m from {} to {}, listening on {}",
+        schedule.interval.num_minutes(),
+        schedule.from.format("%H:%M"),
+        schedule.to.format("%H:%M"),
+        args.socket.display()
+    ));
+
+    let daemon = Arc::new(Daemon {
+        db_path: args.db_path,
+        started_at: now(),
+        state: Mutex::default(),
+        trigger: Notify::new(),
+    });
+    tokio::spawn(daemon.clone().schedule(schedule));
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let daemon = daemon.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = daemon.handle(stream).await {
+                            log(format!("Control connection failed: {err}"));
+                        }
+                    });
+                }
+                Err(err) => log(format!("Could not accept a control connection: {err}")),
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    let _ = std::fs::remove_file(&args.socket);
+    log("Stopped");
+}
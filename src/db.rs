@@ -0,0 +1,493 @@
+use crate::{Cinema, Database, Film, QueryOptions, Seance, CONFIG, NOW};
+use clap::{Args, Subcommand};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use std::{path::PathBuf, sync::Arc, time::Instant};
+
+/// A schema change, applied once to each database. Its version is its position in
+/// `MIGRATIONS` starting at 1, which is stored in `PRAGMA user_version` once applied.
+pub struct Migration {
+    pub description: &'static str,
+    sql: &'static str,
+}
+
+/// Every schema change in order. Only ever append to this list: databases in the wild
+/// have applied the existing entries, so editing them has no effect there.
+pub const MIGRATIONS: &[Migration] = &[
+    // databases created before migrations existed already have these tables, hence
+    // IF NOT EXISTS
+    Migration {
+        description: "Create the programme, favourite, station, geocode and watchlist tables",
+        sql: "CREATE TABLE IF NOT EXISTS cinema (
+                id INTEGER PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                url_path TEXT NOT NULL,
+                address TEXT NOT NULL,
+                image_path TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS film (
+                id INTEGER PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                url_path TEXT NOT NULL,
+                image_path TEXT NOT NULL,
+                director TEXT NOT NULL,
+                release_date TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS seance (
+                id INTEGER PRIMARY KEY NOT NULL,
+                cinema_id INTEGER NOT NULL,
+                film_id INTEGER NOT NULL,
+                datetime TEXT NOT NULL,
+                version TEXT NOT NULL,
+                url TEXT,
+                FOREIGN KEY(cinema_id) REFERENCES cinema(id),
+                FOREIGN KEY(film_id) REFERENCES film(id)
+            );
+            CREATE TABLE IF NOT EXISTS favourite (
+                cinema_url_path TEXT PRIMARY KEY NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS station (
+                id TEXT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                lat REAL NOT NULL,
+                lon REAL NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS geocode (
+                address TEXT PRIMARY KEY NOT NULL,
+                lat REAL NOT NULL,
+                lon REAL NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS watch (
+                id INTEGER PRIMARY KEY NOT NULL,
+                kind TEXT NOT NULL,
+                pattern TEXT NOT NULL,
+                UNIQUE(kind, pattern)
+            );
+            CREATE TABLE IF NOT EXISTS watch_seen (
+                watch_id INTEGER NOT NULL,
+                film_id INTEGER NOT NULL,
+                cinema_url_path TEXT NOT NULL,
+                datetime TEXT NOT NULL,
+                version TEXT NOT NULL,
+                PRIMARY KEY(watch_id, film_id, cinema_url_path, datetime, version),
+                FOREIGN KEY(watch_id) REFERENCES watch(id) ON DELETE CASCADE
+            );",
+    },
+    Migration {
+        description: "Create the archive of scrape runs and the seances they saw",
+        sql: "CREATE TABLE IF NOT EXISTS scrape_run (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                started_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS archive_film (
+                id INTEGER PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                url_path TEXT NOT NULL,
+                image_path TEXT NOT NULL,
+                director TEXT NOT NULL,
+                release_date TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS archive_cinema (
+                url_path TEXT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                address TEXT NOT NULL,
+                image_path TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS archive_seance (
+                id INTEGER PRIMARY KEY NOT NULL,
+                film_id INTEGER NOT NULL,
+                cinema_url_path TEXT NOT NULL,
+                datetime TEXT NOT NULL,
+                version TEXT NOT NULL,
+                url TEXT,
+                UNIQUE(film_id, cinema_url_path, datetime, version),
+                FOREIGN KEY(film_id) REFERENCES archive_film(id),
+                FOREIGN KEY(cinema_url_path) REFERENCES archive_cinema(url_path)
+            );
+            CREATE TABLE IF NOT EXISTS archive_sighting (
+                archive_seance_id INTEGER NOT NULL,
+                run_id INTEGER NOT NULL,
+                url TEXT,
+                PRIMARY KEY(archive_seance_id, run_id),
+                FOREIGN KEY(archive_seance_id) REFERENCES archive_seance(id),
+                FOREIGN KEY(run_id) REFERENCES scrape_run(id)
+            );",
+    },
+    Migration {
+        description: "Index seances by time, film and cinema",
+        sql: "CREATE INDEX IF NOT EXISTS seance_datetime ON seance(datetime);
+            CREATE INDEX IF NOT EXISTS seance_film_id ON seance(film_id);
+            CREATE INDEX IF NOT EXISTS seance_cinema_id ON seance(cinema_id);",
+    },
+    Migration {
+        description: "Flag films discovered on cinema pages",
+        sql: "ALTER TABLE film ADD COLUMN discovered INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE archive_film ADD COLUMN discovered INTEGER NOT NULL DEFAULT 0;",
+    },
+    // seance IDs change with every scrape, so planned seances are kept by what they are
+    Migration {
+        description: "Create the agenda of planned seances",
+        sql: "CREATE TABLE agenda (
+                id INTEGER PRIMARY KEY NOT NULL,
+                film_id INTEGER NOT NULL,
+                film_name TEXT NOT NULL,
+                cinema_url_path TEXT NOT NULL,
+                cinema_name TEXT NOT NULL,
+                datetime TEXT NOT NULL,
+                version TEXT NOT NULL,
+                url TEXT,
+                UNIQUE (film_id, cinema_url_path, datetime, version)
+            );",
+    },
+    Migration {
+        description: "Remember the pages the programme was scraped from",
+        sql: "CREATE TABLE page (
+                url TEXT PRIMARY KEY NOT NULL,
+                hash TEXT NOT NULL
+            );",
+    },
+    // reported once, then kept on the agenda until removed
+    Migration {
+        description: "Mark cancelled agenda entries",
+        sql: "ALTER TABLE agenda ADD COLUMN cancelled INTEGER NOT NULL DEFAULT 0;",
+    },
+];
+
+pub fn schema_version(conn: &rusqlite::Connection) -> rusqlite::Result<usize> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Applies the pending migrations in a single transaction, returning their versions.
+/// On a dry run they are applied then rolled back, which checks they succeed.
+pub fn migrate(
+    conn: &rusqlite::Connection,
+    dry_run: bool,
+) -> rusqlite::Result<Vec<(usize, &'static Migration)>> {
+    let version = schema_version(conn)?;
+    let pending = MIGRATIONS
+        .iter()
+        .enumerate()
+        .map(|(idx, migration)| (idx + 1, migration))
+        .skip(version)
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(pending);
+    }
+    let tx = conn.unchecked_transaction()?;
+    for (version, migration) in &pending {
+        tx.execute_batch(migration.sql)?;
+        tx.pragma_update(None, "user_version", version)?;
+    }
+    if !dry_run {
+        tx.commit()?;
+    }
+    Ok(pending)
+}
+
+/// Why the database can't be used, if it was created by a newer cip whose schema we
+/// don't know
+fn version_error(conn: &rusqlite::Connection) -> rusqlite::Result<Option<String>> {
+    let version = schema_version(conn)?;
+    Ok((version > MIGRATIONS.len()).then(|| {
+        format!(
+            "The database is at schema version {version} but this cip only knows up to {}, \
+            please upgrade cip",
+            MIGRATIONS.len()
+        )
+    }))
+}
+
+/// Exits if the database was created by a newer cip
+pub fn check_version(conn: &rusqlite::Connection) {
+    if let Some(err) = version_error(conn).unwrap() {
+        println!("{err}");
+        std::process::exit(2);
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct DbArgs {
+    /// Database file path
+    #[arg(long, global = true, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    #[command(subcommand)]
+    command: DbCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommands {
+    /// Apply the pending schema migrations
+    Migrate {
+        /// Check the pending migrations apply cleanly without changing the database
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Time inserting and querying a synthetic programme, with and without the indexes,
+    /// write-ahead log and insert transaction
+    Bench {
+        /// Number of seances to insert
+        #[arg(long, default_value_t = 20000)]
+        seances: usize,
+        /// Number of queries to run
+        #[arg(long, default_value_t = 200)]
+        queries: usize,
+    },
+}
+
+/// A programme of `count` seances spread over two weeks
+fn sample_programme(count: usize) -> (Vec<Cinema>, Vec<Film>, Vec<Seance>) {
+    let cinemas = (1..=40)
+        .map(|id| Cinema {
+            id,
+            name: format!("Cinema {id}"),
+            url_path: format!("/cinema/{id}"),
+            address: format!("{id} rue des Écoles 75005 Paris"),
+            image_path: String::new(),
+        })
+        .collect::<Vec<_>>();
+    let films = (1..=(count / 25).max(1) as u64)
+        .map(|id| Film {
+            id,
+            name: format!("Film {id}"),
+            url_path: format!("/film/{id}"),
+            image_path: String::new(),
+            director: String::new(),
+            release_date: "2000".to_string(),
+            discovered: false,
+        })
+        .collect::<Vec<_>>();
+    let seances = (0..count)
+        .map(|idx| Seance {
+            id: idx as u64 + 1,
+            cinema_id: (idx * 7 % cinemas.len()) as u64 + 1,
+            film_id: (idx % films.len()) as u64 + 1,
+            datetime: *NOW + chrono::Duration::minutes((idx * 97 % (14 * 24 * 60)) as i64),
+            version: if idx % 3 == 0 { "VF" } else { "VO" }.to_string(),
+            url: (idx % 2 == 0).then(|| format!("https://example.com/{idx}")),
+        })
+        .collect::<Vec<_>>();
+    (cinemas, films, seances)
+}
+
+fn bench(seances: usize, queries: usize) {
+    let (cinemas, films, seances) = sample_programme(seances);
+    println!(
+        "{} seances inserted, {queries} queries by day, film and cinema\n",
+        seances.len()
+    );
+    println!("{:<8} {:>12} {:>14}", "", "insert", "mean query");
+    let path = std::env::temp_dir().join(format!("cip-bench-{}.db", std::process::id()));
+    for optimised in [false, true] {
+        Database::delete(&path);
+        let db = if optimised {
+            Database::open(&path)
+        } else {
+            // as before: rollback journal, no indexes and autocommitted inserts
+            let pool = Pool::new(SqliteConnectionManager::file(&path)).unwrap();
+            let db = Database(Arc::new(pool));
+            let conn = db.conn().unwrap();
+            migrate(&conn, false).unwrap();
+            conn.execute_batch(
+                "DROP INDEX seance_datetime;
+                DROP INDEX seance_film_id;
+                DROP INDEX seance_cinema_id;",
+            )
+            .unwrap();
+            db
+        };
+        let conn = db.conn().unwrap();
+
+        let start = Instant::now();
+        let tx = optimised.then(|| conn.unchecked_transaction().unwrap());
+        for cinema in &cinemas {
+            conn.insert_cinema(cinema).unwrap();
+        }
+        for film in &films {
+            conn.insert_film(film).unwrap();
+        }
+        for seance in &seances {
+            conn.insert_seance(seance).unwrap();
+        }
+        if let Some(tx) = tx {
+            tx.commit().unwrap();
+        }
+        let insert = start.elapsed();
+
+        let start = Instant::now();
+        for idx in 0..queries {
+            let options = match idx % 3 {
+                0 => QueryOptions {
+                    day: Some(NOW.date_naive() + chrono::Duration::days((idx % 14) as i64)),
+                    ..Default::default()
+                },
+                1 => QueryOptions {
+                    film: Some((idx % films.len()) as u64 + 1),
+                    ..Default::default()
+                },
+                _ => QueryOptions {
+                    cinema: Some((idx % cinemas.len()) as u64 + 1),
+                    ..Default::default()
+                },
+            };
+            conn.query_seances(options).unwrap();
+        }
+        let query = start.elapsed() / queries.max(1) as u32;
+
+        println!(
+            "{:<8} {:>12.2?} {:>14.2?}",
+            if optimised { "after" } else { "before" },
+            insert,
+            query
+        );
+    }
+    Database::delete(&path);
+}
+
+pub async fn db(args: DbArgs) {
+    match args.command {
+        DbCommands::Migrate { dry_run } => {
+            // every other command migrates on open
+            let db = Database::open_unmigrated(&args.db_path);
+            let conn = db.conn().unwrap();
+            check_version(&conn);
+            println!(
+                "Schema version {} of {}",
+                schema_version(&conn).unwrap(),
+                MIGRATIONS.len()
+            );
+            match migrate(&conn, dry_run) {
+                Ok(applied) if applied.is_empty() => println!("Nothing to migrate"),
+                Ok(applied) => {
+                    for (version, migration) in applied {
+                        println!("{version:>3} {}", migration.description);
+                    }
+                    if dry_run {
+                        println!("Dry run: the migrations apply cleanly, nothing was changed");
+                    } else {
+                        println!("Migrated to version {}", MIGRATIONS.len());
+                    }
+                }
+                Err(err) => {
+                    println!("Migration failed, nothing was changed: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        DbCommands::Bench { seances, queries } => bench(seances, queries),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    /// The tables created by cip before it had migrations
+    const BASELINE_SCHEMA: &str = "CREATE TABLE cinema (
+            id INTEGER PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            url_path TEXT NOT NULL,
+            address TEXT NOT NULL,
+            image_path TEXT NOT NULL
+        );
+        CREATE TABLE film (
+            id INTEGER PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            url_path TEXT NOT NULL,
+            image_path TEXT NOT NULL,
+            director TEXT NOT NULL,
+            release_date TEXT NOT NULL
+        );
+        CREATE TABLE seance (
+            id INTEGER PRIMARY KEY NOT NULL,
+            cinema_id INTEGER NOT NULL,
+            film_id INTEGER NOT NULL,
+            datetime TEXT NOT NULL,
+            version TEXT NOT NULL,
+            url TEXT,
+            FOREIGN KEY(cinema_id) REFERENCES cinema(id),
+            FOREIGN KEY(film_id) REFERENCES film(id)
+        );";
+
+    fn schema(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT sql FROM sqlite_master WHERE sql IS NOT NULL ORDER BY name")
+            .unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    fn count(conn: &Connection, table: &str) -> usize {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn migrates_empty_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        let applied = migrate(&conn, false).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn migrates_baseline_database_keeping_its_rows() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        conn.execute_batch(
+            "INSERT INTO cinema VALUES (1, 'Le Champo', '/cinema/champo', '', '');
+            INSERT INTO film VALUES (10, 'Vertigo', '/film/vertigo', '', 'Hitchcock', '1958');
+            INSERT INTO seance VALUES (1, 1, 10, '2023-06-01T20:00:00+02:00', 'VO', NULL);",
+        )
+        .unwrap();
+
+        migrate(&conn, false).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+        assert_eq!(count(&conn, "cinema"), 1);
+        assert_eq!(count(&conn, "film"), 1);
+        assert_eq!(count(&conn, "seance"), 1);
+        let discovered: bool = conn
+            .query_row("SELECT discovered FROM film WHERE id = 10", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(!discovered);
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        let before = schema(&conn);
+
+        let applied = migrate(&conn, true).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        assert_eq!(schema(&conn), before);
+    }
+
+    #[test]
+    fn second_migration_is_a_no_op() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn, false).unwrap();
+        let before = schema(&conn);
+
+        assert!(migrate(&conn, false).unwrap().is_empty());
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+        assert_eq!(schema(&conn), before);
+    }
+
+    #[test]
+    fn refuses_newer_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn, false).unwrap();
+        assert_eq!(version_error(&conn).unwrap(), None);
+
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        assert!(version_error(&conn).unwrap().is_some());
+        assert!(migrate(&conn, false).unwrap().is_empty());
+    }
+}
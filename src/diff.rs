@@ -0,0 +1,371 @@
+use crate::{
+    history::Run, paris_time, parse_past_date, style_id, styled, Connection, Database,
+    QueryOptions, QueryResult, CONFIG, DATETIME_FORMAT, NOW,
+};
+use ansi_term::{Colour, Style};
+use chrono::NaiveTime;
+use clap::Args;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Seances of the same film in the same cinema further apart than this are never
+/// considered moved, but cancelled and added
+const MAX_MOVE_MINUTES: i64 = 48 * 60;
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Scrape run ID (see `cip history runs`) or DD/MM date to compare with, defaults to
+    /// the run before the last one
+    #[arg(long, short = 's')]
+    since: Option<String>,
+    /// Exit with 1 if there are changes
+    #[arg(long)]
+    exit_code: bool,
+}
+
+pub enum Change<'a> {
+    Added(&'a QueryResult),
+    Cancelled(&'a QueryResult),
+    Changed {
+        before: &'a QueryResult,
+        after: &'a QueryResult,
+    },
+}
+
+impl Change<'_> {
+    fn datetime(&self) -> chrono::DateTime<chrono::FixedOffset> {
+        match self {
+            Self::Added(result) | Self::Cancelled(result) => result.seance.datetime,
+            Self::Changed { after, .. } => after.seance.datetime,
+        }
+    }
+
+    fn print(&self) {
+        let format = |result: &QueryResult| {
+            format!(
+                "{} ({}) {}",
+                result.seance.datetime.format(&DATETIME_FORMAT),
+                result.seance.version,
+                result.film.name
+            )
+        };
+        match self {
+            Self::Added(result) => println!(
+                "  {} {} {}",
+                styled(Colour::Green.normal()).paint("+"),
+                style_id(result.seance.id),
+                format(result)
+            ),
+            Self::Cancelled(result) => println!(
+                "  {} {}",
+                styled(Colour::Red.normal()).paint("-"),
+                styled(Style::new().strikethrough()).paint(format(result))
+            ),
+            Self::Changed { before, after } => {
+                let mut changes = Vec::new();
+                if before.seance.datetime != after.seance.datetime {
+                    changes.push(format!(
+                        "moved from {}",
+                        before.seance.datetime.format(&DATETIME_FORMAT)
+                    ));
+                }
+                if before.seance.version != after.seance.version {
+                    changes.push(format!("was {}", before.seance.version));
+                }
+                if before.seance.url != after.seance.url {
+                    changes.push("new booking link".to_string());
+                }
+                println!(
+                    "  {} {} {}, {}",
+                    styled(Colour::Yellow.normal()).paint("~"),
+                    style_id(after.seance.id),
+                    format(after),
+                    changes.join(", ")
+                );
+            }
+        }
+    }
+}
+
+/// Finds the run to compare with from a run ID or a date, in which case it's the last
+/// run started before that day
+fn find_run(conn: &Connection, since: Option<&str>) -> Run {
+    let mut runs = conn.get_runs().unwrap();
+    let run = match since {
+        None => {
+            runs.pop();
+            runs.pop()
+        }
+        Some(since) => match since.parse::<u64>() {
+            Ok(id) => runs.into_iter().find(|run| run.id == id),
+            Err(_) => {
+                let day = parse_past_date(since).unwrap_or_else(|err| {
+                    println!("{err}");
+                    std::process::exit(2);
+                });
+                let day = paris_time(day.and_time(NaiveTime::MIN));
+                runs.into_iter().rev().find(|run| run.started_at < day)
+            }
+        },
+    };
+    run.unwrap_or_else(|| {
+        match since {
+            None => println!("There's no previous scrape run to compare with yet"),
+            Some(since) => println!("No scrape run matches {since}, see `cip history runs`"),
+        }
+        std::process::exit(1);
+    })
+}
+
+/// Pairs up the seances of a film in a cinema before and after, closest times first
+/// and preferring the same version, leaving the unpaired ones as cancelled or added
+pub fn compare<'a>(before: &[&'a QueryResult], after: &[&'a QueryResult]) -> Vec<Change<'a>> {
+    let mut candidates = Vec::new();
+    for (i, b) in before.iter().enumerate() {
+        for (j, a) in after.iter().enumerate() {
+            let minutes = (a.seance.datetime - b.seance.datetime).num_minutes().abs();
+            if minutes <= MAX_MOVE_MINUTES {
+                candidates.push((minutes, b.seance.version != a.seance.version, i, j));
+            }
+        }
+    }
+    candidates.sort();
+    let mut paired_before = vec![false; before.len()];
+    let mut paired_after = vec![false; after.len()];
+    let mut changes = Vec::new();
+    for (_, _, i, j) in candidates {
+        if paired_before[i] || paired_after[j] {
+            continue;
+        }
+        paired_before[i] = true;
+        paired_after[j] = true;
+        let (b, a) = (before[i], after[j]);
+        if b.seance.datetime != a.seance.datetime
+            || b.seance.version != a.seance.version
+            || b.seance.url != a.seance.url
+        {
+            changes.push(Change::Changed {
+                before: b,
+                after: a,
+            });
+        }
+    }
+    changes.extend(
+        before
+            .iter()
+            .zip(paired_before)
+            .filter(|(_, paired)| !paired)
+            .map(|(b, _)| Change::Cancelled(b)),
+    );
+    changes.extend(
+        after
+            .iter()
+            .zip(paired_after)
+            .filter(|(_, paired)| !paired)
+            .map(|(a, _)| Change::Added(a)),
+    );
+    changes
+}
+
+pub async fn diff(args: DiffArgs) {
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    let run = find_run(&conn, args.since.as_deref());
+    // seances which have already taken place aren't cancelled
+    let before = conn
+        .get_run_seances(run.id)
+        .unwrap()
+        .into_iter()
+        .filter(|r| r.seance.datetime >= *NOW)
+        .collect::<Vec<_>>();
+    let after = conn
+        .query_seances(QueryOptions::default())
+        .unwrap()
+        .into_iter()
+        .filter(|r| r.seance.datetime >= *NOW)
+        .collect::<Vec<_>>();
+
+    type Seances<'a> = (Vec<&'a QueryResult>, Vec<&'a QueryResult>);
+    let mut grouped = BTreeMap::<(&str, u64), Seances>::new();
+    for result in &before {
+        grouped
+            .entry((&result.cinema.url_path, result.film.id))
+            .or_default()
+            .0
+            .push(result);
+    }
+    for result in &after {
+        grouped
+            .entry((&result.cinema.url_path, result.film.id))
+            .or_default()
+            .1
+            .push(result);
+    }
+    let mut cinemas = BTreeMap::<&str, (&QueryResult, Vec<Change>)>::new();
+    for ((url_path, _), (b, a)) in &grouped {
+        let changes = compare(b, a);
+        if let Some(first) = b.first().or(a.first()) {
+            if !changes.is_empty() {
+                cinemas
+                    .entry(url_path)
+                    .or_insert_with(|| (first, Vec::new()))
+                    .1
+                    .extend(changes);
+            }
+        }
+    }
+
+    println!(
+        "Changes since scrape run {} of {}\n",
+        run.id,
+        run.started_at.format(&DATETIME_FORMAT)
+    );
+    if cinemas.is_empty() {
+        println!("No changes");
+        return;
+    }
+    let mut cinemas = cinemas.into_values().collect::<Vec<_>>();
+    cinemas.sort_by(|(a, _), (b, _)| a.cinema.name.cmp(&b.cinema.name));
+    for (result, mut changes) in cinemas {
+        changes.sort_by_key(|change| change.datetime());
+        println!(
+            "{}",
+            styled(Style::new().bold()).paint(result.cinema.description())
+        );
+        for change in changes {
+            change.print();
+        }
+        println!();
+    }
+    if args.exit_code {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cinema, Film, Seance};
+    use chrono::DateTime;
+
+    fn seance(id: u64, datetime: &str, version: &str, url: Option<&str>) -> QueryResult {
+        QueryResult {
+            cinema: Cinema {
+                id: 1,
+                name: "Le Champo".to_string(),
+                url_path: "/cinema/champo".to_string(),
+                address: String::new(),
+                image_path: String::new(),
+            },
+            film: Film {
+                id: 10,
+                name: "Vertigo".to_string(),
+                url_path: "/film/vertigo".to_string(),
+                image_path: String::new(),
+                director: String::new(),
+                release_date: String::new(),
+                discovered: false,
+            },
+            seance: Seance {
+                id,
+                cinema_id: 1,
+                film_id: 10,
+                datetime: DateTime::parse_from_rfc3339(datetime).unwrap(),
+                version: version.to_string(),
+                url: url.map(|url| url.to_string()),
+            },
+        }
+    }
+
+    /// The changes as (kind, seance ID before, seance ID after), sorted
+    fn compared(before: &[QueryResult], after: &[QueryResult]) -> Vec<(char, u64, u64)> {
+        let before = before.iter().collect::<Vec<_>>();
+        let after = after.iter().collect::<Vec<_>>();
+        let mut changes = compare(&before, &after)
+            .into_iter()
+            .map(|change| match change {
+                Change::Added(a) => ('+', 0, a.seance.id),
+                Change::Cancelled(b) => ('-', b.seance.id, 0),
+                Change::Changed { before, after } => ('~', before.seance.id, after.seance.id),
+            })
+            .collect::<Vec<_>>();
+        changes.sort();
+        changes
+    }
+
+    #[test]
+    fn unchanged_seances_are_paired_first() {
+        // the 20:00 seance was cancelled, not moved to 14:00
+        let before = [
+            seance(1, "2026-10-19T14:00:00+02:00", "VO", None),
+            seance(2, "2026-10-19T20:00:00+02:00", "VO", None),
+        ];
+        let after = [seance(3, "2026-10-19T14:00:00+02:00", "VO", None)];
+        assert_eq!(compared(&before, &after), [('-', 2, 0)]);
+    }
+
+    #[test]
+    fn closest_seance_is_the_moved_one() {
+        let before = [seance(1, "2026-10-19T20:00:00+02:00", "VO", None)];
+        let after = [
+            seance(2, "2026-10-19T14:00:00+02:00", "VO", None),
+            seance(3, "2026-10-19T21:00:00+02:00", "VO", None),
+        ];
+        assert_eq!(compared(&before, &after), [('+', 0, 2), ('~', 1, 3)]);
+    }
+
+    #[test]
+    fn same_version_is_preferred_on_ties() {
+        let before = [seance(1, "2026-10-19T20:00:00+02:00", "VO", None)];
+        let after = [
+            seance(2, "2026-10-19T19:00:00+02:00", "VF", None),
+            seance(3, "2026-10-19T21:00:00+02:00", "VO", None),
+        ];
+        assert_eq!(compared(&before, &after), [('+', 0, 2), ('~', 1, 3)]);
+    }
+
+    #[test]
+    fn seances_too_far_apart_are_not_moved() {
+        let before = [seance(1, "2026-10-19T20:00:00+02:00", "VO", None)];
+        let at_limit = [seance(2, "2026-10-21T20:00:00+02:00", "VO", None)];
+        assert_eq!(compared(&before, &at_limit), [('~', 1, 2)]);
+        let beyond = [seance(2, "2026-10-21T20:01:00+02:00", "VO", None)];
+        assert_eq!(compared(&before, &beyond), [('+', 0, 2), ('-', 1, 0)]);
+    }
+
+    #[test]
+    fn new_booking_link_is_a_change() {
+        let before = [seance(
+            1,
+            "2026-10-19T20:00:00+02:00",
+            "VO",
+            Some("https://t/1"),
+        )];
+        let same = [seance(
+            2,
+            "2026-10-19T20:00:00+02:00",
+            "VO",
+            Some("https://t/1"),
+        )];
+        assert_eq!(compared(&before, &same), []);
+        let relinked = [seance(
+            2,
+            "2026-10-19T20:00:00+02:00",
+            "VO",
+            Some("https://t/2"),
+        )];
+        assert_eq!(compared(&before, &relinked), [('~', 1, 2)]);
+    }
+
+    #[test]
+    fn unpaired_seances_are_added_or_cancelled() {
+        let seances = [
+            seance(1, "2026-10-19T14:00:00+02:00", "VO", None),
+            seance(2, "2026-10-19T20:00:00+02:00", "VF", None),
+        ];
+        assert_eq!(compared(&[], &seances), [('+', 0, 1), ('+', 0, 2)]);
+        assert_eq!(compared(&seances, &[]), [('-', 1, 0), ('-', 2, 0)]);
+    }
+}
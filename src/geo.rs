@@ -0,0 +1,510 @@
+use crate::{fold, style_id, Connection, Database, QueryResult, CONFIG, DATETIME_FORMAT};
+use clap::{Args, Subcommand};
+use std::{
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// Approximate centres of the Paris arrondissements, used when an address isn't in the
+/// geocoding table
+const ARRONDISSEMENTS: [(f64, f64); 20] = [
+    (48.8626, 2.3363),
+    (48.8683, 2.3428),
+    (48.8630, 2.3600),
+    (48.8543, 2.3576),
+    (48.8445, 2.3507),
+    (48.8491, 2.3328),
+    (48.8562, 2.3122),
+    (48.8727, 2.3125),
+    (48.8770, 2.3375),
+    (48.8761, 2.3608),
+    (48.8591, 2.3800),
+    (48.8400, 2.3880),
+    (48.8283, 2.3623),
+    (48.8292, 2.3265),
+    (48.8401, 2.2930),
+    (48.8637, 2.2769),
+    (48.8873, 2.3067),
+    (48.8925, 2.3484),
+    (48.8871, 2.3848),
+    (48.8634, 2.4011),
+];
+
+/// A postal address split into its street, postcode and city
+#[derive(Debug, PartialEq)]
+pub struct Address {
+    pub street: String,
+    pub postcode: String,
+    pub city: String,
+}
+
+impl Address {
+    pub fn parse(address: &str) -> Self {
+        let words = address.split_whitespace().collect::<Vec<_>>();
+        let postcode = words
+            .iter()
+            .rposition(|w| w.len() == 5 && w.chars().all(|c| c.is_ascii_digit()));
+        match postcode {
+            Some(idx) => Self {
+                street: words[..idx].join(" ").trim_end_matches(',').to_string(),
+                postcode: words[idx].to_string(),
+                city: words[idx + 1..].join(" "),
+            },
+            None => Self {
+                street: words.join(" "),
+                postcode: String::new(),
+                city: String::new(),
+            },
+        }
+    }
+
+    /// The Paris arrondissement, 1 to 20
+    pub fn arrondissement(&self) -> Option<u32> {
+        let number = match self.postcode.as_str() {
+            "75116" => 16,
+            postcode => postcode.strip_prefix("750")?.parse().ok()?,
+        };
+        (1..=20).contains(&number).then_some(number)
+    }
+
+    /// Key used to look the address up in the geocoding table
+    pub fn key(&self) -> String {
+        format!("{} {}", normalize_street(&self.street), self.postcode)
+    }
+}
+
+/// Folds a street so that "51 bis Bd Saint-Michel" and "51bis boulevard saint michel" match
+fn normalize_street(street: &str) -> String {
+    let street = fold(street)
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>();
+    let mut words = Vec::<String>::new();
+    for word in street.split_whitespace() {
+        let word = match word {
+            "bd" | "boul" => "boulevard",
+            "av" | "ave" => "avenue",
+            "pl" => "place",
+            "r" => "rue",
+            "fg" | "fbg" => "faubourg",
+            "st" => "saint",
+            "ste" => "sainte",
+            "b" => "bis",
+            "t" => "ter",
+            word => word,
+        };
+        match words.last_mut() {
+            Some(number)
+                if matches!(word, "bis" | "ter" | "quater")
+                    && number.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                number.push_str(word)
+            }
+            _ => words.push(word.to_string()),
+        }
+    }
+    words.join(" ")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coordinates {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Coordinates {
+    /// Great-circle distance in kilometres
+    pub fn distance(&self, other: &Coordinates) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+        let a = (dlat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.).sin().powi(2);
+        6371. * 2. * a.sqrt().asin()
+    }
+
+    /// Parses "LAT,LON" or the name of a location saved in the config file
+    pub fn resolve(location: &str) -> Result<Self, String> {
+        location.parse().or_else(|_| {
+            CONFIG
+                .locations
+                .get(location)
+                .ok_or_else(|| format!("Unknown location {location}"))?
+                .parse()
+        })
+    }
+}
+
+impl FromStr for Coordinates {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Coordinates should be in format LAT,LON: {s}");
+        let (lat, lon) = s.split_once(',').ok_or_else(err)?;
+        Ok(Self {
+            lat: lat.trim().parse().map_err(|_| err())?,
+            lon: lon.trim().parse().map_err(|_| err())?,
+        })
+    }
+}
+
+/// Parses a distance such as "2km", "500m" or "1.5" (kilometres)
+pub fn parse_radius(radius: &str) -> Result<f64, String> {
+    let radius = radius.trim().to_lowercase();
+    let (value, scale) = if let Some(km) = radius.strip_suffix("km") {
+        (km, 1.)
+    } else if let Some(m) = radius.strip_suffix('m') {
+        (m, 0.001)
+    } else {
+        (radius.as_str(), 1.)
+    };
+    value
+        .trim()
+        .parse::<f64>()
+        .map(|value| value * scale)
+        .map_err(|_| format!("Radius should be in format 2km or 500m: {radius}"))
+}
+
+/// Where a cinema is, and whether the position is exact or an arrondissement centre
+#[derive(Clone, Copy, Debug)]
+pub struct Location {
+    pub coordinates: Coordinates,
+    pub exact: bool,
+}
+
+pub fn locate(conn: &Connection, address: &Address) -> Option<Location> {
+    if let Some(coordinates) = conn.get_geocode(&address.key()).unwrap() {
+        return Some(Location {
+            coordinates,
+            exact: true,
+        });
+    }
+    let (lat, lon) = ARRONDISSEMENTS[address.arrondissement()? as usize - 1];
+    Some(Location {
+        coordinates: Coordinates { lat, lon },
+        exact: false,
+    })
+}
+
+/// A public transport station imported from GTFS stops
+#[derive(Debug)]
+pub struct Station {
+    pub name: String,
+    pub coordinates: Coordinates,
+}
+
+/// Stations closest to a point, nearest first
+pub fn nearest_stations<'a>(
+    stations: &'a [Station],
+    coordinates: &Coordinates,
+    count: usize,
+) -> Vec<(&'a Station, f64)> {
+    let mut nearest = stations
+        .iter()
+        .map(|station| (station, station.coordinates.distance(coordinates)))
+        .collect::<Vec<_>>();
+    nearest.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+    nearest.truncate(count);
+    nearest
+}
+
+/// Streets aren't straight lines
+const DETOUR_FACTOR: f64 = 1.3;
+const WALKING_SPEED: f64 = 4.5;
+/// Average metro speed including stops and changes
+const METRO_SPEED: f64 = 22.;
+/// Time spent getting to the platform and waiting for a train
+const METRO_OVERHEAD: f64 = 6.;
+/// Distance to a station assumed when no stations have been imported
+const DEFAULT_STATION_DISTANCE: f64 = 0.4;
+
+/// Walking time in minutes for a straight-line distance in kilometres
+fn walking_time(distance: f64) -> f64 {
+    distance * DETOUR_FACTOR / WALKING_SPEED * 60.
+}
+
+/// Metro time in minutes between two stations, walking to and from them
+fn metro_time(access: f64, distance: f64, egress: f64) -> f64 {
+    walking_time(access)
+        + METRO_OVERHEAD
+        + distance * DETOUR_FACTOR / METRO_SPEED * 60.
+        + walking_time(egress)
+}
+
+#[derive(Args, Debug)]
+pub struct RouteArgs {
+    /// Seance to leave
+    from: u64,
+    /// Seance to get to
+    to: u64,
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Length of the first seance in minutes, trailers included
+    #[arg(long, default_value_t = 120)]
+    runtime: i64,
+}
+
+/// Estimated time to get from one place to another, walking or by metro
+pub struct Travel {
+    pub distance: f64,
+    pub walk: f64,
+    pub metro: f64,
+    /// Stations used for the metro estimate, if any have been imported
+    pub stations: Option<(String, String)>,
+}
+
+impl Travel {
+    pub fn estimate(stations: &[Station], from: &Coordinates, to: &Coordinates) -> Self {
+        let distance = from.distance(to);
+        let walk = walking_time(distance);
+        match (
+            nearest_stations(stations, from, 1).pop(),
+            nearest_stations(stations, to, 1).pop(),
+        ) {
+            (Some((start, access)), Some((end, egress))) => Self {
+                distance,
+                walk,
+                metro: metro_time(access, start.coordinates.distance(&end.coordinates), egress),
+                stations: Some((start.name.clone(), end.name.clone())),
+            },
+            _ => Self {
+                distance,
+                walk,
+                metro: metro_time(DEFAULT_STATION_DISTANCE, distance, DEFAULT_STATION_DISTANCE),
+                stations: None,
+            },
+        }
+    }
+
+    /// Minutes taken by the fastest option
+    pub fn minutes(&self) -> f64 {
+        self.walk.min(self.metro)
+    }
+}
+
+pub async fn route(args: RouteArgs) {
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    let get_seance = |id| {
+        conn.get_seance(id).unwrap().unwrap_or_else(|| {
+            println!("Seance {} not found", style_id(id));
+            std::process::exit(2);
+        })
+    };
+    let (from, to) = (get_seance(args.from), get_seance(args.to));
+    let end = from.seance.datetime + chrono::Duration::minutes(args.runtime);
+    let gap = (to.seance.datetime - end).num_minutes();
+    let describe = |result: &QueryResult| {
+        format!(
+            "{} {} at {}",
+            style_id(result.seance.id),
+            result.film.name,
+            result.cinema.description()
+        )
+    };
+    println!(
+        "From:     {}, {} to ~{}",
+        describe(&from),
+        from.seance.datetime.format(&DATETIME_FORMAT),
+        end.format("%H:%M")
+    );
+    println!(
+        "To:       {}, {}",
+        describe(&to),
+        to.seance.datetime.format(&DATETIME_FORMAT)
+    );
+
+    let travel = if from.cinema.id == to.cinema.id {
+        println!("Same cinema");
+        0.
+    } else {
+        let locate = |result: &QueryResult| {
+            locate(&conn, &result.cinema.address()).unwrap_or_else(|| {
+                println!("Could not locate {}", result.cinema.name);
+                std::process::exit(1);
+            })
+        };
+        let stations = conn.get_stations().unwrap();
+        let travel = Travel::estimate(
+            &stations,
+            &locate(&from).coordinates,
+            &locate(&to).coordinates,
+        );
+        println!("Distance: {:.1} km", travel.distance);
+        println!("Walk:     {:.0} min", travel.walk);
+        match &travel.stations {
+            Some((start, end)) => println!("Metro:    {:.0} min ({start} → {end})", travel.metro),
+            None => println!("Metro:    {:.0} min (no stations imported)", travel.metro),
+        }
+        travel.minutes()
+    };
+    let spare = gap as f64 - travel;
+    if gap < 0 {
+        println!("Not possible, the seances overlap by {} min", -gap);
+    } else if spare >= 0. {
+        println!("Gap:      {gap} min, {spare:.0} min to spare");
+    } else {
+        println!("Gap:      {gap} min, {:.0} min short", -spare);
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct GeoArgs {
+    /// Database file path
+    #[arg(long, global = true, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    #[command(subcommand)]
+    command: GeoCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum GeoCommands {
+    /// Import address coordinates from a BAN CSV extract (adresses-75.csv)
+    Import {
+        /// CSV file path
+        path: PathBuf,
+    },
+    /// Import stations from a GTFS stops.txt file
+    Stations {
+        /// stops.txt file path
+        path: PathBuf,
+    },
+    /// Show the parsed addresses, coordinates and nearest stations of the cinemas
+    Cinemas,
+}
+
+#[derive(serde::Deserialize)]
+struct Stop {
+    stop_id: String,
+    stop_name: String,
+    stop_lat: f64,
+    stop_lon: f64,
+    #[serde(default)]
+    location_type: Option<u8>,
+    #[serde(default)]
+    parent_station: Option<String>,
+}
+
+/// Imports stations, or stops which don't belong to one, from a GTFS stops.txt
+fn import_stations(conn: &Connection, path: &PathBuf) -> csv::Result<usize> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let tx = conn.unchecked_transaction().unwrap();
+    conn.execute("DELETE FROM station", ()).unwrap();
+    let mut count = 0;
+    for stop in reader.deserialize() {
+        let stop: Stop = stop?;
+        let is_station = match stop.location_type {
+            Some(1) => true,
+            None | Some(0) => stop.parent_station.is_none_or(|p| p.is_empty()),
+            _ => false,
+        };
+        if !is_station {
+            continue;
+        }
+        let station = Station {
+            name: stop.stop_name,
+            coordinates: Coordinates {
+                lat: stop.stop_lat,
+                lon: stop.stop_lon,
+            },
+        };
+        conn.insert_station(&stop.stop_id, &station).unwrap();
+        count += 1;
+    }
+    tx.commit().unwrap();
+    Ok(count)
+}
+
+/// Reads the `numero`, `rep`, `nom_voie`, `code_postal`, `lat` and `lon` columns of a
+/// semicolon separated BAN extract
+fn import(conn: &Connection, path: &PathBuf) -> std::io::Result<usize> {
+    let mut lines = BufReader::new(std::fs::File::open(path)?).lines();
+    let header = lines.next().transpose()?.unwrap_or_default();
+    let columns = header.split(';').collect::<Vec<_>>();
+    let column = |name: &str| {
+        columns.iter().position(|c| *c == name).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Missing column {name}"),
+            )
+        })
+    };
+    let (numero, rep, voie, postcode, lat, lon) = (
+        column("numero")?,
+        column("rep")?,
+        column("nom_voie")?,
+        column("code_postal")?,
+        column("lat")?,
+        column("lon")?,
+    );
+    let tx = conn.unchecked_transaction().unwrap();
+    let mut count = 0;
+    for line in lines {
+        let line = line?;
+        let fields = line.split(';').collect::<Vec<_>>();
+        let field = |idx: usize| fields.get(idx).copied().unwrap_or_default();
+        let coordinates = match (field(lat).parse(), field(lon).parse()) {
+            (Ok(lat), Ok(lon)) => Coordinates { lat, lon },
+            _ => continue,
+        };
+        let address = Address {
+            street: format!("{} {} {}", field(numero), field(rep), field(voie)),
+            postcode: field(postcode).to_string(),
+            city: String::new(),
+        };
+        conn.insert_geocode(&address.key(), &coordinates).unwrap();
+        count += 1;
+    }
+    tx.commit().unwrap();
+    Ok(count)
+}
+
+pub async fn geo(args: GeoArgs) {
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    match args.command {
+        GeoCommands::Import { path } => match import(&conn, &path) {
+            Ok(count) => println!("Imported {count} addresses"),
+            Err(err) => {
+                println!("Could not import {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        },
+        GeoCommands::Stations { path } => match import_stations(&conn, &path) {
+            Ok(count) => println!("Imported {count} stations"),
+            Err(err) => {
+                println!("Could not import {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        },
+        GeoCommands::Cinemas => {
+            let stations = conn.get_stations().unwrap();
+            for cinema in conn.get_cinemas().unwrap() {
+                let address = cinema.address();
+                println!("{} {}", style_id(cinema.id), cinema.name);
+                println!("  Street:         {}", address.street);
+                println!("  Postcode:       {} {}", address.postcode, address.city);
+                if let Some(arrondissement) = address.arrondissement() {
+                    println!("  Arrondissement: {arrondissement}");
+                }
+                match locate(&conn, &address) {
+                    Some(location) => {
+                        println!(
+                            "  Coordinates:    {:.5},{:.5}{}",
+                            location.coordinates.lat,
+                            location.coordinates.lon,
+                            if location.exact { "" } else { " (approximate)" }
+                        );
+                        for (station, distance) in
+                            nearest_stations(&stations, &location.coordinates, 2)
+                        {
+                            println!("  Station:        {} ({distance:.1} km)", station.name);
+                        }
+                    }
+                    None => println!("  Coordinates:    unknown"),
+                }
+                println!();
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{style_id, styled, Cinema, Database, CONFIG};
+use ansi_term::Style;
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use clap::{Args, Subcommand};
+use std::{collections::BTreeMap, path::PathBuf};
+
+const DATE_FORMAT: &str = "%d/%m/%Y";
+
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    /// Database file path
+    #[arg(long, global = true, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    #[command(subcommand)]
+    command: HistoryCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommands {
+    /// Show every seance of a film ever scraped, by venue
+    Film {
+        /// Film ID or title
+        film: String,
+    },
+    /// List the scrape runs recorded in the archive
+    Runs,
+}
+
+/// An archived seance
+pub struct Sighting {
+    pub cinema: Cinema,
+    pub datetime: DateTime<FixedOffset>,
+    pub version: String,
+    /// Start of the first scrape run which saw the seance
+    pub first_seen: DateTime<FixedOffset>,
+    /// Whether the last scrape run before the seance still had it, i.e. it wasn't
+    /// cancelled or moved, or is still programmed if it's yet to come
+    pub screened: bool,
+}
+
+pub struct Run {
+    pub id: u64,
+    pub started_at: DateTime<FixedOffset>,
+    /// Seances seen by the run
+    pub seances: usize,
+}
+
+/// The seances a venue gave of a film
+struct Venue<'a> {
+    cinema: &'a Cinema,
+    seances: usize,
+    first: NaiveDate,
+    last: NaiveDate,
+}
+
+fn film_history(db: &Database, pattern: &str) {
+    let conn = db.conn().unwrap();
+    let films = conn
+        .get_archived_films()
+        .unwrap()
+        .into_iter()
+        .filter(|film| film.matches(pattern))
+        .collect::<Vec<_>>();
+    let film = match films.as_slice() {
+        [] => {
+            println!("No archived film matches {pattern}");
+            std::process::exit(1);
+        }
+        [film] => film,
+        _ => {
+            println!("Several archived films match {pattern}:");
+            for film in &films {
+                println!("{} {}", style_id(film.id), film.description());
+            }
+            std::process::exit(2);
+        }
+    };
+    let sightings = conn.get_film_history(film.id).unwrap();
+    let announced = sightings.iter().map(|s| s.first_seen).min();
+    let dropped = sightings.iter().filter(|s| !s.screened).count();
+    let sightings = sightings
+        .into_iter()
+        .filter(|s| s.screened)
+        .collect::<Vec<_>>();
+    println!(
+        "{} {}",
+        style_id(film.id),
+        styled(Style::new().bold()).paint(film.description())
+    );
+    if !film.director.is_empty() {
+        println!("  by {}", film.director);
+    }
+    let (Some(first), Some(last)) = (sightings.first(), sightings.last()) else {
+        if dropped > 0 {
+            println!("  {dropped} seances announced, all cancelled or moved");
+        } else {
+            println!("  No archived seances");
+        }
+        return;
+    };
+    let (first_day, last_day) = (first.datetime.date_naive(), last.datetime.date_naive());
+    println!(
+        "  On screen from {} to {} ({} days)",
+        first_day.format(DATE_FORMAT),
+        last_day.format(DATE_FORMAT),
+        (last_day - first_day).num_days() + 1
+    );
+    if let Some(announced) = announced {
+        println!("  First announced on {}", announced.format(DATE_FORMAT));
+    }
+    let vo = sightings.iter().filter(|s| s.version == "VO").count();
+    println!(
+        "  {} seances, {} VO / {} VF",
+        sightings.len(),
+        vo,
+        sightings.len() - vo
+    );
+    if dropped > 0 {
+        println!("  {dropped} more announced, then cancelled or moved");
+    }
+
+    let mut venues = BTreeMap::<&str, Venue>::new();
+    for sighting in &sightings {
+        let day = sighting.datetime.date_naive();
+        let venue = venues
+            .entry(&sighting.cinema.url_path)
+            .or_insert_with(|| Venue {
+                cinema: &sighting.cinema,
+                seances: 0,
+                first: day,
+                last: day,
+            });
+        venue.seances += 1;
+        venue.first = venue.first.min(day);
+        venue.last = venue.last.max(day);
+    }
+    let mut venues = venues.into_values().collect::<Vec<_>>();
+    venues.sort_by_key(|venue| (std::cmp::Reverse(venue.seances), venue.first));
+    println!();
+    println!("{}", styled(Style::new().bold()).paint("Venues"));
+    for venue in venues {
+        println!(
+            "  {:<40} {:>4} seances  {} - {}",
+            venue.cinema.description(),
+            venue.seances,
+            venue.first.format(DATE_FORMAT),
+            venue.last.format(DATE_FORMAT)
+        );
+    }
+}
+
+fn runs(db: &Database) {
+    let conn = db.conn().unwrap();
+    let runs = conn.get_runs().unwrap();
+    if runs.is_empty() {
+        println!("No scrape runs yet, run `cip scrape` first");
+        return;
+    }
+    for run in runs {
+        println!(
+            "{} {} {:>5} seances",
+            style_id(run.id),
+            run.started_at.format("%d/%m/%Y %H:%M"),
+            run.seances
+        );
+    }
+}
+
+pub async fn history(args: HistoryArgs) {
+    let db = Database::open(&args.db_path);
+    match args.command {
+        HistoryCommands::Film { film } => film_history(&db, &film),
+        HistoryCommands::Runs => runs(&db),
+    }
+}
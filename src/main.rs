@@ -2,32 +2,60 @@ use ansi_term::{ANSIGenericString, Style};
 use chrono::{prelude::*, DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use directories::ProjectDirs;
+use futures::StreamExt;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
 use reqwest::Url;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use soup::prelude::*;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::Duration,
 };
-use tokio::sync::Mutex;
+use tokio::sync::mpsc;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+mod agenda;
+mod cache;
+mod check;
+mod daemon;
+mod db;
+mod diff;
+mod geo;
+mod history;
+mod notify;
+mod plan;
+mod stats;
+mod together;
+mod tui;
+
+static COLOR: AtomicBool = AtomicBool::new(true);
 
 lazy_static::lazy_static! {
-    static ref ROOT_URL: Url = Url::parse("https://www.cip-paris.fr").unwrap();
+    static ref CONFIG: Config = Config::load();
+    static ref ROOT_URL: Url = Url::parse(&CONFIG.base_url).expect("Base URL should be a valid URL");
     static ref FILMS_URL: Url = ROOT_URL.join("/json/movies").unwrap();
     static ref CINEMAS_URL: Url = ROOT_URL.join("/json/cinemas").unwrap();
     static ref PROG_BAR_STYLE: ProgressStyle =
                 ProgressStyle::with_template("  {msg:26} {bar:40}   {pos}/{len}")
                     .unwrap();
-    static ref PARIS_OFFSET: FixedOffset = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
-    static ref NOW: DateTime<FixedOffset> = Utc::now().with_timezone(&*PARIS_OFFSET);
+    static ref TIME_ZONE: Option<FixedOffset> = CONFIG
+        .time_zone
+        .as_ref()
+        .map(|time_zone| parse_offset(time_zone).expect("Time zone should be in format +HH:MM"));
+    static ref NOW: DateTime<FixedOffset> = paris_now();
     static ref PROJECT_DIRS: ProjectDirs = ProjectDirs::from("com.github", "jpopesculian", "cip").unwrap();
     static ref DEFAULT_DB_PATH: PathBuf = PROJECT_DIRS.data_dir().join("data.db");
+    static ref CONFIG_PATH: PathBuf = PROJECT_DIRS.config_dir().join("config.toml");
+    static ref DATETIME_FORMAT: String = format!("{} %H:%M", CONFIG.date_format);
     static ref DAY_START: NaiveTime = NaiveTime::from_hms_opt(4, 0, 0).unwrap();
+    static ref EVENING_START: NaiveTime = NaiveTime::from_hms_opt(18, 0, 0).unwrap();
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,7 +76,10 @@ impl Cinema {
         format!("{} ({})", self.name, self.zip())
     }
     fn zip(&self) -> String {
-        self.address.rsplit(' ').nth(1).unwrap().to_string()
+        self.address().postcode
+    }
+    fn address(&self) -> geo::Address {
+        geo::Address::parse(&self.address)
     }
     fn url(&self) -> Url {
         ROOT_URL.join(&self.url_path).unwrap()
@@ -70,6 +101,9 @@ struct Film {
     director: String,
     #[serde(rename = "releaseDate")]
     release_date: String,
+    /// Found on a cinema page but missing from the film list
+    #[serde(default)]
+    discovered: bool,
 }
 
 fn deserialize_null_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
@@ -88,6 +122,18 @@ impl Film {
     fn url(&self) -> Url {
         ROOT_URL.join(&self.url_path).unwrap()
     }
+    /// Whether the film has this ID, or a title containing this text ignoring accents
+    fn matches(&self, pattern: &str) -> bool {
+        let pattern = fold(pattern.trim());
+        self.id.to_string() == pattern || fold(&self.name).contains(&pattern)
+    }
+    fn release(&self) -> Option<NaiveDate> {
+        let date = self.release_date.trim();
+        ["%d/%m/%Y", "%Y-%m-%d"]
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+            .or_else(|| NaiveDate::from_ymd_opt(date.parse().ok()?, 1, 1))
+    }
     // fn image(&self) -> Url {
     //     ROOT_URL.join(&self.image_path).unwrap()
     // }
@@ -103,9 +149,18 @@ struct Seance {
     url: Option<String>,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl Seance {
+    /// The programme day, which starts at `DAY_START` rather than midnight
+    fn day(&self) -> NaiveDate {
+        (self.datetime - DAY_START.signed_duration_since(NaiveTime::MIN)).date_naive()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 enum Version {
+    #[serde(rename = "VO", alias = "vo")]
     Original,
+    #[serde(rename = "VF", alias = "vf")]
     French,
 }
 
@@ -118,11 +173,14 @@ impl Version {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct QueryOptions {
     day: Option<NaiveDate>,
     time: Option<NaiveTime>,
     version: Option<Version>,
+    favourites: bool,
+    film: Option<u64>,
+    cinema: Option<u64>,
 }
 
 impl QueryOptions {
@@ -132,15 +190,11 @@ impl QueryOptions {
         }
         let start = self.day.unwrap_or_else(|| NOW.date_naive());
         let time = self.time.unwrap_or(*DAY_START);
-        NaiveDateTime::new(start, time)
-            .and_local_timezone(*PARIS_OFFSET)
-            .earliest()
+        Some(paris_time(NaiveDateTime::new(start, time)))
     }
     fn before(&self) -> Option<DateTime<FixedOffset>> {
         let day = (self.after()? + chrono::Duration::hours(24)).date_naive();
-        NaiveDateTime::new(day, *DAY_START)
-            .and_local_timezone(*PARIS_OFFSET)
-            .earliest()
+        Some(paris_time(NaiveDateTime::new(day, *DAY_START)))
     }
 }
 
@@ -151,20 +205,293 @@ struct QueryResult {
     seance: Seance,
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum WatchKind {
+    Title,
+    Director,
+    Film,
+}
+
+impl WatchKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Director => "director",
+            Self::Film => "film",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Watch {
+    id: u64,
+    kind: WatchKind,
+    pattern: String,
+}
+
+impl Watch {
+    fn description(&self) -> String {
+        format!("{}: {}", self.kind.as_str(), self.pattern)
+    }
+    fn matches(&self, film: &Film) -> bool {
+        match self.kind {
+            WatchKind::Title => fold(&film.name).contains(&fold(&self.pattern)),
+            WatchKind::Director => fold(&film.director).contains(&fold(&self.pattern)),
+            WatchKind::Film => film.id.to_string() == self.pattern,
+        }
+    }
+}
+
+/// Lowercase and strip accents so that "mepris" matches "Le Mépris"
+fn fold(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// A named set of default query filters
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Profile {
+    /// Only show this version
+    version: Option<Version>,
+    /// Only show favourite cinemas
+    favourites: bool,
+    /// Only show seances after HH:MM
+    after: Option<String>,
+    /// Group by cinemas or films
+    group: Option<GroupBy>,
+    /// Sort cinemas by distance to LAT,LON or a saved location
+    near: Option<String>,
+    /// Only show cinemas within this distance of `near`, e.g. 2km
+    radius: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// Database file path
+    db_path: PathBuf,
+    /// Website to scrape
+    base_url: String,
+    /// Group query results by cinemas or films
+    group: GroupBy,
+    /// Colour output
+    color: bool,
+    /// Format used to display dates
+    date_format: String,
+    /// UTC offset of seance times all year round, +HH:MM, defaults to Paris time, which
+    /// follows summer time
+    time_zone: Option<String>,
+    /// Number of cinema pages downloaded in parallel
+    concurrency: usize,
+    /// Profile applied when none is given on the command line
+    default_profile: Option<String>,
+    profiles: BTreeMap<String, Profile>,
+    /// Named LAT,LON locations, e.g. home
+    locations: BTreeMap<String, String>,
+    /// Scrape schedule and notifications of `cip daemon`
+    daemon: daemon::DaemonConfig,
+    /// Why the config file or environment variables are invalid
+    #[serde(skip)]
+    error: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            db_path: DEFAULT_DB_PATH.clone(),
+            base_url: "https://www.cip-paris.fr".to_string(),
+            group: GroupBy::Cinema,
+            color: true,
+            date_format: "%d/%m".to_string(),
+            time_zone: None,
+            concurrency: 16,
+            default_profile: None,
+            profiles: BTreeMap::new(),
+            locations: BTreeMap::new(),
+            daemon: daemon::DaemonConfig::default(),
+            error: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file and applies `CIP_*` environment variable overrides
+    fn load() -> Self {
+        let mut config = match std::fs::read_to_string(&*CONFIG_PATH) {
+            // reported once the command is known, as `config edit` can still fix it
+            Ok(config) => toml::from_str(&config).unwrap_or_else(|err| Self {
+                error: Some(format!(
+                    "Invalid config file {}: {err}",
+                    CONFIG_PATH.display()
+                )),
+                ..Self::default()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => panic!("Could not read {}: {err}", CONFIG_PATH.display()),
+        };
+        if let Err(err) = config.apply_env().and_then(|()| config.validate()) {
+            config.error.get_or_insert(err);
+        }
+        config
+    }
+
+    fn apply_env(&mut self) -> Result<(), String> {
+        fn var(name: &str) -> Option<String> {
+            std::env::var(name).ok().filter(|v| !v.is_empty())
+        }
+        fn parse<T: std::str::FromStr>(name: &str, value: String) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid value for {name}: {value}"))
+        }
+        if let Some(db_path) = var("CIP_DB_PATH") {
+            self.db_path = db_path.into();
+        }
+        if let Some(base_url) = var("CIP_BASE_URL") {
+            self.base_url = base_url;
+        }
+        if let Some(group) = var("CIP_GROUP") {
+            self.group = GroupBy::from_str(&group, true)
+                .map_err(|_| format!("Invalid value for CIP_GROUP: {group}"))?;
+        }
+        if let Some(color) = var("CIP_COLOR") {
+            self.color = parse("CIP_COLOR", color)?;
+        }
+        if var("NO_COLOR").is_some() {
+            self.color = false;
+        }
+        if let Some(date_format) = var("CIP_DATE_FORMAT") {
+            self.date_format = date_format;
+        }
+        if let Some(time_zone) = var("CIP_TIME_ZONE") {
+            self.time_zone = Some(time_zone);
+        }
+        if let Some(concurrency) = var("CIP_CONCURRENCY") {
+            self.concurrency = parse("CIP_CONCURRENCY", concurrency)?;
+        }
+        if let Some(profile) = var("CIP_PROFILE") {
+            self.default_profile = Some(profile);
+        }
+        Ok(())
+    }
+
+    /// Checks the values which are only parsed when they're used
+    fn validate(&self) -> Result<(), String> {
+        Url::parse(&self.base_url)
+            .map_err(|err| format!("Invalid base_url {}: {err}", self.base_url))?;
+        if chrono::format::StrftimeItems::new(&self.date_format)
+            .any(|item| item == chrono::format::Item::Error)
+        {
+            return Err(format!("Invalid date_format {}", self.date_format));
+        }
+        if let Some(time_zone) = &self.time_zone {
+            if parse_offset(time_zone).is_none() {
+                return Err(format!(
+                    "Invalid time_zone {time_zone}, it should be in format +HH:MM"
+                ));
+            }
+        }
+        for (name, profile) in &self.profiles {
+            let invalid =
+                |field: &str, err: String| format!("Invalid {field} in profile {name}: {err}");
+            if let Some(after) = &profile.after {
+                try_parse_time(after).map_err(|err| invalid("after", err))?;
+            }
+            if let Some(radius) = &profile.radius {
+                geo::parse_radius(radius).map_err(|err| invalid("radius", err))?;
+            }
+        }
+        self.daemon.validate()
+    }
+
+    fn profile(&self, name: Option<&str>) -> Option<&Profile> {
+        let name = name.or(self.default_profile.as_deref())?;
+        match self.profiles.get(name) {
+            Some(profile) => Some(profile),
+            None => {
+                println!("Profile {name} not found in {}", CONFIG_PATH.display());
+                std::process::exit(2);
+            }
+        }
+    }
+}
+
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    DateTime::parse_from_str(&format!("2000-01-01 00:00 {offset}"), "%Y-%m-%d %H:%M %:z")
+        .ok()
+        .map(|datetime| *datetime.offset())
+}
+
+/// The UTC offset in Paris at a UTC time: the configured time zone, or +02:00 in summer
+/// time, from 01:00 UTC on the last Sunday of March to 01:00 UTC on the last Sunday of
+/// October, and +01:00 otherwise
+fn paris_offset(utc: NaiveDateTime) -> FixedOffset {
+    if let Some(offset) = *TIME_ZONE {
+        return offset;
+    }
+    let clock_change = |month: u32| {
+        let last = NaiveDate::from_ymd_opt(utc.year(), month + 1, 1)
+            .unwrap()
+            .pred_opt()
+            .unwrap();
+        let sunday = last - chrono::Duration::days(last.weekday().num_days_from_sunday().into());
+        sunday.and_hms_opt(1, 0, 0).unwrap()
+    };
+    let summer = clock_change(3) <= utc && utc < clock_change(10);
+    FixedOffset::east_opt(if summer { 2 * 3600 } else { 3600 }).unwrap()
+}
+
+/// A time in Paris, the earlier one when clocks go back
+fn paris_time(local: NaiveDateTime) -> DateTime<FixedOffset> {
+    let offset = paris_offset(local - chrono::Duration::hours(2));
+    local.and_local_timezone(offset).unwrap()
+}
+
+fn paris_now() -> DateTime<FixedOffset> {
+    let now = Utc::now();
+    now.with_timezone(&paris_offset(now.naive_utc()))
+}
+
 pub struct Database(Arc<Pool<SqliteConnectionManager>>);
 
 impl Database {
     pub fn open(path: impl AsRef<Path>) -> Self {
+        let db = Self::open_unmigrated(path);
+        let conn = db.conn().unwrap();
+        db::check_version(&conn);
+        if let Err(err) = db::migrate(&conn, false) {
+            println!("Could not migrate the database: {err}");
+            std::process::exit(1);
+        }
+        db
+    }
+
+    /// Opens the database without applying pending migrations
+    pub fn open_unmigrated(path: impl AsRef<Path>) -> Self {
         std::fs::create_dir_all(path.as_ref().parent().unwrap()).unwrap();
-        let manager = SqliteConnectionManager::file(path);
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.execute_batch(
+                "PRAGMA journal_mode = WAL;
+                PRAGMA foreign_keys = ON;",
+            )
+        });
         let pool = Pool::new(manager).unwrap();
         Self(Arc::new(pool))
     }
 
     pub fn delete(path: impl AsRef<Path>) {
         let path = path.as_ref();
-        if path.exists() {
-            std::fs::remove_file(path).unwrap();
+        // along with the write-ahead log
+        for suffix in ["", "-wal", "-shm"] {
+            let mut file = path.as_os_str().to_owned();
+            file.push(suffix);
+            let file = Path::new(&file);
+            if file.exists() {
+                std::fs::remove_file(file).unwrap();
+            }
         }
     }
 
@@ -183,19 +510,32 @@ impl std::ops::Deref for Connection {
 }
 
 impl Connection {
-    fn create_cinemas(&self) -> rusqlite::Result<usize> {
-        self.execute(
-            "CREATE TABLE cinema (
-                id INTEGER PRIMARY KEY NOT NULL,
-                name TEXT NOT NULL,
-                url_path TEXT NOT NULL,
-                address TEXT NOT NULL,
-                image_path TEXT NOT NULL
-            )",
-            (),
+    fn clear_programme(&self) -> rusqlite::Result<()> {
+        self.execute_batch(
+            "DELETE FROM seance;
+            DELETE FROM film;
+            DELETE FROM cinema;
+            DELETE FROM page;",
         )
     }
 
+    /// Hashes of the pages the current programme was parsed from, by URL
+    fn get_page_hashes(&self) -> rusqlite::Result<HashMap<String, String>> {
+        let mut stmt = self.prepare_cached("SELECT url, hash FROM page")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    fn insert_page_hash(&self, url: &str, hash: &str) -> rusqlite::Result<usize> {
+        let mut statement =
+            self.prepare_cached("INSERT OR REPLACE INTO page (url, hash) VALUES (?1, ?2)")?;
+        statement.execute([url, hash])
+    }
+
+    fn delete_page_hash(&self, url: &str) -> rusqlite::Result<usize> {
+        self.execute("DELETE FROM page WHERE url = ?1", [url])
+    }
+
     fn insert_cinema(&self, cinema: &Cinema) -> rusqlite::Result<usize> {
         let mut statement = self.prepare_cached(
             "INSERT INTO cinema
@@ -211,25 +551,11 @@ impl Connection {
         ])
     }
 
-    fn create_films(&self) -> rusqlite::Result<usize> {
-        self.execute(
-            "CREATE TABLE film (
-                id INTEGER PRIMARY KEY NOT NULL,
-                name TEXT NOT NULL,
-                url_path TEXT NOT NULL,
-                image_path TEXT NOT NULL,
-                director TEXT NOT NULL,
-                release_date TEXT NOT NULL
-            )",
-            (),
-        )
-    }
-
     fn insert_film(&self, film: &Film) -> rusqlite::Result<usize> {
         let mut statement = self.prepare_cached(
             "INSERT INTO film
-                (id, name, url_path, image_path, director, release_date)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                (id, name, url_path, image_path, director, release_date, discovered)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
         )?;
 
         statement.execute(rusqlite::params![
@@ -239,25 +565,10 @@ impl Connection {
             &film.image_path,
             &film.director,
             &film.release_date,
+            film.discovered,
         ])
     }
 
-    fn create_seances(&self) -> rusqlite::Result<usize> {
-        self.execute(
-            "CREATE TABLE seance (
-                id INTEGER PRIMARY KEY NOT NULL,
-                cinema_id INTEGER NOT NULL,
-                film_id INTEGER NOT NULL,
-                datetime TEXT NOT NULL,
-                version TEXT NOT NULL,
-                url TEXT,
-                FOREIGN KEY(cinema_id) REFERENCES cinema(id),
-                FOREIGN KEY(film_id) REFERENCES film(id)
-            )",
-            (),
-        )
-    }
-
     fn insert_seance(&self, seance: &Seance) -> rusqlite::Result<usize> {
         let mut statement = self.prepare_cached(
             "INSERT INTO seance
@@ -285,6 +596,16 @@ impl Connection {
         if let Some(version) = options.version {
             where_clauses.push(format!("version = '{}'", version.short()));
         }
+        if options.favourites {
+            where_clauses
+                .push("cinema.url_path IN (SELECT cinema_url_path FROM favourite)".to_string());
+        }
+        if let Some(film) = options.film {
+            where_clauses.push(format!("film_id = {film}"));
+        }
+        if let Some(cinema) = options.cinema {
+            where_clauses.push(format!("cinema_id = {cinema}"));
+        }
         let where_clause = if !where_clauses.is_empty() {
             format!("WHERE {}", where_clauses.join(" AND "))
         } else {
@@ -294,7 +615,8 @@ impl Connection {
             "SELECT
                 seance.id, cinema_id, film_id, datetime, version, url,
                 cinema.name, cinema.url_path, cinema.address, cinema.image_path,
-                film.name, film.url_path, film.image_path, film.director, film.release_date
+                film.name, film.url_path, film.image_path, film.director, film.release_date,
+                film.discovered
             FROM seance
             INNER JOIN cinema ON cinema.id = seance.cinema_id
             INNER JOIN film ON film.id = seance.film_id
@@ -317,6 +639,7 @@ impl Connection {
                     image_path: row.get(12)?,
                     director: row.get(13)?,
                     release_date: row.get(14)?,
+                    discovered: row.get(15)?,
                 },
                 seance: Seance {
                     id: row.get(0)?,
@@ -336,7 +659,8 @@ impl Connection {
             "SELECT
                 seance.id, cinema_id, film_id, datetime, version, url,
                 cinema.name, cinema.url_path, cinema.address, cinema.image_path,
-                film.name, film.url_path, film.image_path, film.director, film.release_date
+                film.name, film.url_path, film.image_path, film.director, film.release_date,
+                film.discovered
             FROM seance
             INNER JOIN cinema ON cinema.id = seance.cinema_id
             INNER JOIN film ON film.id = seance.film_id
@@ -359,6 +683,7 @@ impl Connection {
                     image_path: row.get(12)?,
                     director: row.get(13)?,
                     release_date: row.get(14)?,
+                    discovered: row.get(15)?,
                 },
                 seance: Seance {
                     id: row.get(0)?,
@@ -372,249 +697,1288 @@ impl Connection {
         })?;
         rows.next().transpose()
     }
-}
 
-fn parse_date(date: &str) -> NaiveDate {
-    let (day, month) = date
-        .split_once('/')
-        .expect("Date should be in format DD/MM");
-    let day = day.parse::<u32>().unwrap();
-    let month = month.parse::<u32>().unwrap();
-    let date = NaiveDate::from_ymd_opt(NOW.year(), month, day).unwrap();
-    if date < NOW.date_naive() {
-        NaiveDate::from_ymd_opt(NOW.year() + 1, month, day).unwrap()
-    } else {
-        date
+    fn get_cinemas(&self) -> rusqlite::Result<Vec<Cinema>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT id, name, url_path, address, image_path FROM cinema ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Cinema {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                url_path: row.get(2)?,
+                address: row.get(3)?,
+                image_path: row.get(4)?,
+            })
+        })?;
+        rows.collect()
     }
-}
 
-fn parse_time(time: &str) -> NaiveTime {
-    NaiveTime::parse_from_str(time, "%H:%M").expect("Time should be in format HH:MM")
-}
+    fn insert_favourite(&self, cinema: &Cinema) -> rusqlite::Result<usize> {
+        self.execute(
+            "INSERT OR IGNORE INTO favourite (cinema_url_path) VALUES (?1)",
+            [&cinema.url_path],
+        )
+    }
 
-#[derive(Args, Debug)]
-struct ScrapeArgs {
-    /// Database file path
-    #[arg(long, default_value = DEFAULT_DB_PATH.display().to_string())]
-    db_path: PathBuf,
-}
+    fn delete_favourite(&self, cinema: &Cinema) -> rusqlite::Result<usize> {
+        self.execute(
+            "DELETE FROM favourite WHERE cinema_url_path = ?1",
+            [&cinema.url_path],
+        )
+    }
 
-async fn scrape(args: ScrapeArgs) {
-    let progress = MultiProgress::new();
+    fn get_favourites(&self) -> rusqlite::Result<Vec<Cinema>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT id, name, url_path, address, image_path FROM cinema
+            WHERE url_path IN (SELECT cinema_url_path FROM favourite)
+            ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Cinema {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                url_path: row.get(2)?,
+                address: row.get(3)?,
+                image_path: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
 
-    let future_cinemas = async {
-        let prog = progress.add(ProgressBar::new_spinner().with_message("Downloading cinemas"));
-        prog.enable_steady_tick(Duration::from_millis(100));
-        let mut cinemas: Vec<Cinema> = reqwest::get(CINEMAS_URL.as_str())
-            .await
-            .unwrap()
-            .json()
-            .await
-            .unwrap();
-        for (id, cinema) in cinemas.iter_mut().enumerate() {
-            cinema.id = id as u64 + 1;
-        }
-        prog.disable_steady_tick();
-        prog.finish_with_message("Downloaded cinemas");
-        cinemas
-    };
-    let future_films = async {
-        let prog = progress.add(ProgressBar::new_spinner().with_message("Downloading films"));
-        prog.enable_steady_tick(Duration::from_millis(100));
-        let films: Vec<Film> = reqwest::get(FILMS_URL.as_str())
-            .await
-            .unwrap()
-            .json()
-            .await
-            .unwrap();
-        prog.disable_steady_tick();
-        prog.finish_with_message("Downloaded films");
-        films
-    };
-    let (cinemas, films) = futures::future::join(future_cinemas, future_films).await;
+    fn insert_geocode(
+        &self,
+        address: &str,
+        coordinates: &geo::Coordinates,
+    ) -> rusqlite::Result<usize> {
+        let mut statement = self.prepare_cached(
+            "INSERT OR REPLACE INTO geocode
+                (address, lat, lon)
+                VALUES (?1, ?2, ?3)",
+        )?;
+        statement.execute(rusqlite::params![address, coordinates.lat, coordinates.lon])
+    }
 
-    let seances = Arc::new(Mutex::new(Vec::<Seance>::new()));
-    futures::future::join_all(cinemas.iter().map(|cinema| async {
-        let prog = progress.add(
-            ProgressBar::new_spinner()
-                .with_message(format!("Downloading sceances: {}", cinema.name)),
-        );
-        prog.enable_steady_tick(Duration::from_millis(100));
-        let cinema_html = reqwest::get(cinema.url())
-            .await
-            .unwrap()
-            .text()
-            .await
-            .unwrap();
-        let cinema_soup = Soup::new(&cinema_html);
-        prog.disable_steady_tick();
-        prog.set_style(PROG_BAR_STYLE.clone());
-        prog.set_message(cinema.name.clone());
-        prog.set_length(cinema_soup.class("session-date").find_all().count() as u64);
-        for film_soup in cinema_soup.class("movie-results-container").find_all() {
-            let url_path = film_soup
-                .class("poster")
-                .find()
-                .unwrap()
-                .get("href")
-                .unwrap();
-            let film = films.iter().find(|f| f.url_path == url_path).unwrap();
-            for seance_soup in film_soup.class("session-date").find_all() {
-                let date = seance_soup
-                    .class("sessionDate")
-                    .find()
-                    .unwrap()
-                    .text()
-                    .trim()
-                    .split_once(' ')
-                    .unwrap()
-                    .1
-                    .to_string();
-                let time = seance_soup
-                    .class("time")
-                    .find()
-                    .unwrap()
-                    .text()
-                    .trim()
-                    .to_string();
-                let datetime = NaiveDateTime::new(parse_date(&date), parse_time(&time))
-                    .and_local_timezone(*PARIS_OFFSET)
-                    .earliest()
-                    .unwrap();
-                let version = seance_soup
-                    .class("version")
-                    .find()
-                    .unwrap()
-                    .text()
-                    .trim()
-                    .to_string();
-                let url = seance_soup
-                    .tag("a")
-                    .find()
-                    .and_then(|link| link.get("href"));
-                let mut seances = seances.lock().await;
-                let exists = seances.iter().any(|s| {
-                    s.cinema_id == cinema.id
-                        && s.film_id == film.id
-                        && s.datetime == datetime
-                        && s.version == version
-                        && s.url == url
-                });
-                if !exists {
-                    let id = seances.len() as u64 + 1;
-                    seances.push(Seance {
-                        id,
-                        cinema_id: cinema.id,
-                        film_id: film.id,
-                        datetime,
-                        version,
-                        url,
-                    });
-                }
-                // conn.insert_seance(&seance).unwrap();
-                prog.inc(1);
-            }
-        }
-        prog.finish();
-    }))
-    .await;
+    fn get_geocode(&self, address: &str) -> rusqlite::Result<Option<geo::Coordinates>> {
+        let mut stmt = self.prepare_cached("SELECT lat, lon FROM geocode WHERE address = ?1")?;
+        let mut rows = stmt.query_map([address], |row| {
+            Ok(geo::Coordinates {
+                lat: row.get(0)?,
+                lon: row.get(1)?,
+            })
+        })?;
+        rows.next().transpose()
+    }
 
-    Database::delete(&args.db_path);
-    let db = Database::open(&args.db_path);
-    let conn = db.conn().unwrap();
+    fn insert_station(&self, id: &str, station: &geo::Station) -> rusqlite::Result<usize> {
+        let mut statement = self.prepare_cached(
+            "INSERT OR REPLACE INTO station
+                (id, name, lat, lon)
+                VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        statement.execute(rusqlite::params![
+            id,
+            &station.name,
+            station.coordinates.lat,
+            station.coordinates.lon,
+        ])
+    }
 
-    let prog = progress.add(
-        ProgressBar::new(cinemas.len() as u64)
-            .with_style(PROG_BAR_STYLE.clone())
-            .with_message("Inserting cinemas"),
-    );
-    conn.create_cinemas().unwrap();
-    for cinema in &cinemas {
-        conn.insert_cinema(cinema).unwrap();
-        prog.inc(1);
+    fn get_stations(&self) -> rusqlite::Result<Vec<geo::Station>> {
+        let mut stmt = self.prepare_cached("SELECT name, lat, lon FROM station")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(geo::Station {
+                name: row.get(0)?,
+                coordinates: geo::Coordinates {
+                    lat: row.get(1)?,
+                    lon: row.get(2)?,
+                },
+            })
+        })?;
+        rows.collect()
     }
-    prog.finish_with_message("Inserted cinemas");
 
-    let prog = progress.add(
-        ProgressBar::new(films.len() as u64)
-            .with_style(PROG_BAR_STYLE.clone())
-            .with_message("Inserting films"),
-    );
-    conn.create_films().unwrap();
-    for film in &films {
-        conn.insert_film(film).unwrap();
-        prog.inc(1);
+    fn insert_watch(&self, kind: WatchKind, pattern: &str) -> rusqlite::Result<usize> {
+        let mut statement = self.prepare_cached(
+            "INSERT OR IGNORE INTO watch
+                (kind, pattern)
+                VALUES (?1, ?2)",
+        )?;
+        statement.execute(rusqlite::params![kind.as_str(), pattern])
     }
-    prog.finish_with_message("Inserted films");
 
-    let seances = seances.lock().await;
-    let prog = progress.add(
-        ProgressBar::new(seances.len() as u64)
-            .with_style(PROG_BAR_STYLE.clone())
-            .with_message("Inserting seances"),
-    );
-    conn.create_seances().unwrap();
-    for seance in seances.iter() {
-        conn.insert_seance(seance).unwrap();
-        prog.inc(1);
+    fn delete_watch(&self, id: u64) -> rusqlite::Result<usize> {
+        self.execute("DELETE FROM watch_seen WHERE watch_id = ?1", [id])?;
+        self.execute("DELETE FROM watch WHERE id = ?1", [id])
     }
-    prog.finish_with_message("Inserted seances");
-}
 
-#[derive(Clone, Copy, Debug, ValueEnum)]
-enum GroupBy {
-    Cinema,
-    Film,
-}
+    fn get_watches(&self) -> rusqlite::Result<Vec<Watch>> {
+        let mut stmt =
+            self.prepare_cached("SELECT id, kind, pattern FROM watch ORDER BY id ASC")?;
+        let rows = stmt.query_map([], |row| {
+            let kind: String = row.get(1)?;
+            Ok(Watch {
+                id: row.get(0)?,
+                kind: WatchKind::from_str(&kind, false).unwrap(),
+                pattern: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
 
-#[derive(Args, Debug)]
-struct QueryArgs {
-    /// Database file path
-    #[arg(long, default_value = DEFAULT_DB_PATH.display().to_string())]
-    db_path: PathBuf,
-    /// Day to query DD/MM
-    #[arg(long, short = 'd')]
-    day: Option<String>,
-    /// Time to query after HH:MM
-    #[arg(long, short = 't')]
-    time: Option<String>,
-    /// Show VF only
-    #[arg(long)]
-    vf: bool,
-    /// Show VO only
-    #[arg(long)]
-    vo: bool,
-    /// Group by cinemas or films
-    #[arg(long, short = 'g', value_enum, default_value_t = GroupBy::Cinema)]
-    group: GroupBy,
-}
+    /// Marks a seance as reported for a watch, returning whether it was new
+    fn mark_watch_seen(&self, watch: &Watch, result: &QueryResult) -> rusqlite::Result<bool> {
+        let mut statement = self.prepare_cached(
+            "INSERT OR IGNORE INTO watch_seen
+                (watch_id, film_id, cinema_url_path, datetime, version)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        let inserted = statement.execute(rusqlite::params![
+            watch.id,
+            result.film.id,
+            &result.cinema.url_path,
+            result.seance.datetime.to_rfc3339(),
+            &result.seance.version,
+        ])?;
+        Ok(inserted > 0)
+    }
 
-type Grouping = BTreeMap<u64, (String, BTreeMap<u64, (String, Vec<QueryResult>)>)>;
+    fn insert_agenda(&self, result: &QueryResult) -> rusqlite::Result<usize> {
+        let mut statement = self.prepare_cached(
+            "INSERT INTO agenda
+                (film_id, film_name, cinema_url_path, cinema_name, datetime, version, url)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                ON CONFLICT (film_id, cinema_url_path, datetime, version)
+                    DO UPDATE SET url = excluded.url, cancelled = 0",
+        )?;
+        statement.execute(rusqlite::params![
+            result.film.id,
+            &result.film.name,
+            &result.cinema.url_path,
+            &result.cinema.name,
+            result.seance.datetime.to_rfc3339(),
+            &result.seance.version,
+            &result.seance.url,
+        ])
+    }
 
-fn style_id(id: u64) -> ANSIGenericString<'static, str> {
-    Style::new().dimmed().paint(format!("[{id}]"))
-}
+    /// Points an agenda entry at the seance it became
+    fn update_agenda(&self, id: u64, result: &QueryResult) -> rusqlite::Result<usize> {
+        let mut statement = self.prepare_cached(
+            "UPDATE agenda
+                SET datetime = ?2, version = ?3, url = ?4
+                WHERE id = ?1",
+        )?;
+        statement.execute(rusqlite::params![
+            id,
+            result.seance.datetime.to_rfc3339(),
+            &result.seance.version,
+            &result.seance.url,
+        ])
+    }
 
-async fn query(args: QueryArgs) {
+    fn cancel_agenda(&self, id: u64) -> rusqlite::Result<usize> {
+        self.execute("UPDATE agenda SET cancelled = 1 WHERE id = ?1", [id])
+    }
+
+    fn delete_agenda(&self, id: u64) -> rusqlite::Result<usize> {
+        self.execute("DELETE FROM agenda WHERE id = ?1", [id])
+    }
+
+    fn get_agenda(&self) -> rusqlite::Result<Vec<agenda::Entry>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT
+                id, film_id, film_name, cinema_url_path, cinema_name, datetime, version, url,
+                cancelled
+                FROM agenda
+                ORDER BY datetime ASC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(agenda::Entry {
+                id: row.get(0)?,
+                film_id: row.get(1)?,
+                film_name: row.get(2)?,
+                cinema_url_path: row.get(3)?,
+                cinema_name: row.get(4)?,
+                datetime: row.get(5)?,
+                version: row.get(6)?,
+                url: row.get(7)?,
+                cancelled: row.get(8)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Copies the current programme into the append-only archive as a new scrape run,
+    /// returning the run ID. Meant to run in the transaction which inserted the programme.
+    fn archive_programme(&self, started_at: DateTime<FixedOffset>) -> rusqlite::Result<u64> {
+        self.execute(
+            "INSERT INTO scrape_run (started_at) VALUES (?1)",
+            [started_at.to_rfc3339()],
+        )?;
+        let run_id = self.last_insert_rowid() as u64;
+        self.execute_batch(
+            "INSERT INTO archive_film
+                (id, name, url_path, image_path, director, release_date, discovered)
+                SELECT id, name, url_path, image_path, director, release_date, discovered
+                FROM film WHERE true
+                ON CONFLICT(id) DO UPDATE SET
+                    name = excluded.name,
+                    url_path = excluded.url_path,
+                    image_path = excluded.image_path,
+                    director = excluded.director,
+                    release_date = excluded.release_date,
+                    discovered = excluded.discovered;
+            INSERT INTO archive_cinema
+                (url_path, name, address, image_path)
+                SELECT url_path, name, address, image_path FROM cinema WHERE true
+                ON CONFLICT(url_path) DO UPDATE SET
+                    name = excluded.name,
+                    address = excluded.address,
+                    image_path = excluded.image_path;
+            INSERT OR IGNORE INTO archive_seance
+                (film_id, cinema_url_path, datetime, version, url)
+                SELECT seance.film_id, cinema.url_path, seance.datetime, seance.version, seance.url
+                FROM seance JOIN cinema ON seance.cinema_id = cinema.id;",
+        )?;
+        self.execute(
+            "INSERT OR IGNORE INTO archive_sighting (archive_seance_id, run_id, url)
+                SELECT archive_seance.id, ?1, seance.url
+                FROM seance
+                JOIN cinema ON seance.cinema_id = cinema.id
+                JOIN archive_seance ON archive_seance.film_id = seance.film_id
+                    AND archive_seance.cinema_url_path = cinema.url_path
+                    AND archive_seance.datetime = seance.datetime
+                    AND archive_seance.version = seance.version",
+            [run_id],
+        )?;
+        Ok(run_id)
+    }
+
+    fn get_archived_films(&self) -> rusqlite::Result<Vec<Film>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT id, name, url_path, image_path, director, release_date, discovered
+            FROM archive_film ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Film {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                url_path: row.get(2)?,
+                image_path: row.get(3)?,
+                director: row.get(4)?,
+                release_date: row.get(5)?,
+                discovered: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Every archived seance of a film, in time order
+    fn get_film_history(&self, film_id: u64) -> rusqlite::Result<Vec<history::Sighting>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT archive_cinema.url_path, archive_cinema.name, archive_cinema.address,
+                archive_cinema.image_path, archive_seance.datetime, archive_seance.version,
+                (SELECT MIN(scrape_run.started_at) FROM archive_sighting
+                    JOIN scrape_run ON archive_sighting.run_id = scrape_run.id
+                    WHERE archive_sighting.archive_seance_id = archive_seance.id),
+                -- times are compared as instants, their offsets may differ
+                EXISTS (SELECT 1 FROM archive_sighting
+                    WHERE archive_sighting.archive_seance_id = archive_seance.id
+                    AND archive_sighting.run_id = (SELECT id FROM scrape_run
+                        WHERE julianday(started_at) < julianday(archive_seance.datetime)
+                        ORDER BY julianday(started_at) DESC LIMIT 1))
+            FROM archive_seance
+            JOIN archive_cinema ON archive_seance.cinema_url_path = archive_cinema.url_path
+            WHERE archive_seance.film_id = ?1
+            ORDER BY archive_seance.datetime ASC",
+        )?;
+        let rows = stmt.query_map([film_id], |row| {
+            Ok(history::Sighting {
+                cinema: Cinema {
+                    id: 0,
+                    url_path: row.get(0)?,
+                    name: row.get(1)?,
+                    address: row.get(2)?,
+                    image_path: row.get(3)?,
+                },
+                datetime: row.get(4)?,
+                version: row.get(5)?,
+                first_seen: row.get(6)?,
+                screened: row.get(7)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// When each film in the archive was first seen by a scrape run
+    fn get_films_first_seen(&self) -> rusqlite::Result<HashMap<u64, DateTime<FixedOffset>>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT archive_seance.film_id, MIN(scrape_run.started_at)
+            FROM archive_seance
+            JOIN archive_sighting ON archive_sighting.archive_seance_id = archive_seance.id
+            JOIN scrape_run ON archive_sighting.run_id = scrape_run.id
+            GROUP BY archive_seance.film_id",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    fn get_runs(&self) -> rusqlite::Result<Vec<history::Run>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT id, started_at,
+                (SELECT COUNT(*) FROM archive_sighting WHERE run_id = scrape_run.id)
+            FROM scrape_run ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(history::Run {
+                id: row.get(0)?,
+                started_at: row.get(1)?,
+                seances: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// The seances seen by a scrape run, with the booking URL they had then. Cinemas
+    /// which are still listed get their current ID, the others 0.
+    fn get_run_seances(&self, run_id: u64) -> rusqlite::Result<Vec<QueryResult>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT
+                archive_seance.id, COALESCE(cinema.id, 0), archive_seance.film_id,
+                archive_seance.datetime, archive_seance.version, archive_sighting.url,
+                archive_cinema.name, archive_cinema.url_path, archive_cinema.address,
+                archive_cinema.image_path,
+                archive_film.name, archive_film.url_path, archive_film.image_path,
+                archive_film.director, archive_film.release_date, archive_film.discovered
+            FROM archive_sighting
+            INNER JOIN archive_seance ON archive_seance.id = archive_sighting.archive_seance_id
+            INNER JOIN archive_cinema ON archive_cinema.url_path = archive_seance.cinema_url_path
+            INNER JOIN archive_film ON archive_film.id = archive_seance.film_id
+            LEFT JOIN cinema ON cinema.url_path = archive_cinema.url_path
+            WHERE archive_sighting.run_id = ?1
+            ORDER BY archive_seance.datetime ASC",
+        )?;
+        let rows = stmt.query_map([run_id], |row| {
+            Ok(QueryResult {
+                cinema: Cinema {
+                    id: row.get(1)?,
+                    name: row.get(6)?,
+                    url_path: row.get(7)?,
+                    address: row.get(8)?,
+                    image_path: row.get(9)?,
+                },
+                film: Film {
+                    id: row.get(2)?,
+                    name: row.get(10)?,
+                    url_path: row.get(11)?,
+                    image_path: row.get(12)?,
+                    director: row.get(13)?,
+                    release_date: row.get(14)?,
+                    discovered: row.get(15)?,
+                },
+                seance: Seance {
+                    id: row.get(0)?,
+                    cinema_id: row.get(1)?,
+                    film_id: row.get(2)?,
+                    datetime: row.get(3)?,
+                    version: row.get(4)?,
+                    url: row.get(5)?,
+                },
+            })
+        })?;
+        rows.collect()
+    }
+
+    fn get_films(&self) -> rusqlite::Result<Vec<Film>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT id, name, url_path, image_path, director, release_date, discovered
+            FROM film ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Film {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                url_path: row.get(2)?,
+                image_path: row.get(3)?,
+                director: row.get(4)?,
+                release_date: row.get(5)?,
+                discovered: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    fn get_max_seance_id(&self) -> rusqlite::Result<u64> {
+        self.query_row("SELECT COALESCE(MAX(id), 0) FROM seance", [], |row| {
+            row.get(0)
+        })
+    }
+
+    /// Deletes the seances of a cinema, only those of some films if any are given
+    fn delete_seances(&self, cinema_id: u64, film_ids: &HashSet<u64>) -> rusqlite::Result<usize> {
+        if film_ids.is_empty() {
+            return self.execute("DELETE FROM seance WHERE cinema_id = ?1", [cinema_id]);
+        }
+        let mut statement =
+            self.prepare_cached("DELETE FROM seance WHERE cinema_id = ?1 AND film_id = ?2")?;
+        let mut deleted = 0;
+        for film_id in film_ids {
+            deleted += statement.execute([cinema_id, *film_id])?;
+        }
+        Ok(deleted)
+    }
+
+    /// The number of seances a scrape run saw in each cinema, by cinema URL
+    fn get_run_cinema_counts(&self, run_id: u64) -> rusqlite::Result<HashMap<String, usize>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT archive_seance.cinema_url_path, COUNT(*)
+            FROM archive_sighting
+            INNER JOIN archive_seance ON archive_seance.id = archive_sighting.archive_seance_id
+            WHERE archive_sighting.run_id = ?1
+            GROUP BY archive_seance.cinema_url_path",
+        )?;
+        let rows = stmt.query_map([run_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+}
+
+fn parse_date(date: &str) -> NaiveDate {
+    try_parse_date(date).expect("Date should be in format DD/MM")
+}
+
+/// Parses DD/MM as the next such day
+fn try_parse_date(date: &str) -> Option<NaiveDate> {
+    let (day, month) = date.split_once('/')?;
+    let day = day.parse::<u32>().ok()?;
+    let month = month.parse::<u32>().ok()?;
+    let date = NaiveDate::from_ymd_opt(NOW.year(), month, day)?;
+    if date < NOW.date_naive() {
+        NaiveDate::from_ymd_opt(NOW.year() + 1, month, day)
+    } else {
+        Some(date)
+    }
+}
+
+/// Parses DD/MM as the last such day, which is up to 4 years ago for 29/02
+fn parse_past_date(date: &str) -> Result<NaiveDate, String> {
+    let err = || format!("Date should be in format DD/MM: {date}");
+    let (day, month) = date.split_once('/').ok_or_else(err)?;
+    let day = day.parse::<u32>().map_err(|_| err())?;
+    let month = month.parse::<u32>().map_err(|_| err())?;
+    (0..=4)
+        .filter_map(|years| NaiveDate::from_ymd_opt(NOW.year() - years, month, day))
+        .find(|date| *date <= NOW.date_naive())
+        .ok_or_else(err)
+}
+
+fn try_parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| format!("Time should be in format HH:MM: {time}"))
+}
+
+fn parse_time(time: &str) -> NaiveTime {
+    try_parse_time(time).expect("Time should be in format HH:MM")
+}
+
+#[derive(Args, Debug)]
+struct ScrapeArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Number of cinema pages to download in parallel
+    #[arg(long, short = 'j', default_value_t = CONFIG.concurrency)]
+    concurrency: usize,
+    /// Check the site still has the expected layout without changing the database
+    #[arg(long, conflicts_with_all = ["cinema", "film"])]
+    check: bool,
+    /// Only re-fetch these cinemas, by ID or name, and replace their seances
+    #[arg(long, value_delimiter = ',')]
+    cinema: Vec<String>,
+    /// Only replace the seances of these films, by ID or title, re-fetching the cinemas
+    /// currently showing them (or those given with --cinema)
+    #[arg(long, value_delimiter = ',')]
+    film: Vec<String>,
+    /// Use cached pages younger than this without asking the site if they changed,
+    /// e.g. 30m or 1h
+    #[arg(long, value_parser = plan::parse_minutes, default_value = "0")]
+    max_age: i64,
+    /// Download and parse every page, without reading or writing the HTTP cache
+    #[arg(long)]
+    no_cache: bool,
+}
+
+async fn scrape(args: ScrapeArgs) {
+    if args.check {
+        return check::check(&args.db_path, args.concurrency).await;
+    }
+    if !args.cinema.is_empty() || !args.film.is_empty() {
+        return scrape_partial(args).await;
+    }
+    let progress = MultiProgress::new();
+    let cache = cache::HttpCache::new(!args.no_cache, args.max_age);
+    let client = reqwest::Client::new();
+    let hashes = Arc::new(page_hashes(&args.db_path, args.no_cache));
+
+    let future_cinemas = async {
+        let prog = progress.add(ProgressBar::new_spinner().with_message("Downloading cinemas"));
+        prog.enable_steady_tick(Duration::from_millis(100));
+        let body = cache.get(&client, CINEMAS_URL.as_str()).await.unwrap();
+        let mut cinemas: Vec<Cinema> = serde_json::from_str(&body).unwrap();
+        for (id, cinema) in cinemas.iter_mut().enumerate() {
+            cinema.id = id as u64 + 1;
+        }
+        prog.disable_steady_tick();
+        prog.finish_with_message("Downloaded cinemas");
+        cinemas
+    };
+    let future_films = async {
+        let prog = progress.add(ProgressBar::new_spinner().with_message("Downloading films"));
+        prog.enable_steady_tick(Duration::from_millis(100));
+        let body = cache.get(&client, FILMS_URL.as_str()).await.unwrap();
+        let films: Vec<Film> = serde_json::from_str(&body).unwrap();
+        prog.disable_steady_tick();
+        prog.finish_with_message("Downloaded films");
+        (films, page_hash(&body))
+    };
+    let (cinemas, (films, films_hash)) = futures::future::join(future_cinemas, future_films).await;
+    let films_unchanged = hashes.get(FILMS_URL.as_str()) == Some(&films_hash);
+
+    // Unchanged cinema pages aren't parsed again, their seances are carried over from the
+    // current programme. That's only sound if they'd be parsed against the same film list.
+    // The seances of pages which couldn't be downloaded are carried over regardless.
+    let mut previous = HashMap::<String, Vec<QueryResult>>::new();
+    {
+        let db = Database::open(&args.db_path);
+        for result in db
+            .conn()
+            .unwrap()
+            .query_seances(Default::default())
+            .unwrap()
+        {
+            previous
+                .entry(result.cinema.url_path.clone())
+                .or_default()
+                .push(result);
+        }
+    }
+    let carried = Arc::new(if films_unchanged {
+        previous.keys().cloned().collect::<HashSet<_>>()
+    } else {
+        HashSet::new()
+    });
+
+    let film_ids = Arc::new(
+        films
+            .iter()
+            .map(|film| (film.url_path.clone(), film.id))
+            .collect::<HashMap<_, _>>(),
+    );
+    let pages = cinemas
+        .iter()
+        .map(|cinema| {
+            (
+                cinema.id,
+                cinema.name.clone(),
+                cinema.url_path.clone(),
+                cinema.url(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let (sender, receiver) = mpsc::channel(args.concurrency.max(1));
+    let prog = progress.add(ProgressBar::new_spinner().with_message("Collecting seances"));
+    let db_path = args.db_path.clone();
+    let writer = tokio::task::spawn_blocking(move || {
+        write_programme(
+            &db_path, cinemas, films, films_hash, previous, receiver, prog,
+        )
+    });
+
+    let progress = &progress;
+    let (cache, client) = (&cache, &client);
+    let downloaded =
+        futures::stream::iter(pages.into_iter().map(|(cinema_id, name, url_path, url)| {
+            let film_ids = film_ids.clone();
+            let carried = carried.clone();
+            let hashes = hashes.clone();
+            let sender = sender.clone();
+            async move {
+                let prog = progress.add(
+                    ProgressBar::new_spinner().with_message(format!("Downloading seances: {name}")),
+                );
+                prog.enable_steady_tick(Duration::from_millis(100));
+                let cinema_html = match cache.get(client, url.as_str()).await {
+                    Ok(cinema_html) => cinema_html,
+                    // an error page isn't an empty programme
+                    Err(err) => {
+                        prog.disable_steady_tick();
+                        prog.finish_with_message(format!("{name} (failed, seances kept)"));
+                        prog.println(format!("{name}: {err}"));
+                        sender
+                            .send(Scraped::Failed {
+                                cinema_id,
+                                url_path,
+                            })
+                            .await
+                            .unwrap();
+                        return false;
+                    }
+                };
+                let hash = page_hash(&cinema_html);
+                let unchanged = hashes.get(url.as_str()) == Some(&hash);
+                sender
+                    .send(Scraped::Page {
+                        url: url.to_string(),
+                        hash,
+                    })
+                    .await
+                    .unwrap();
+                if unchanged && carried.contains(&url_path) {
+                    prog.disable_steady_tick();
+                    prog.finish_with_message(format!("{name} (unchanged)"));
+                    sender
+                        .send(Scraped::Unchanged {
+                            cinema_id,
+                            url_path,
+                        })
+                        .await
+                        .unwrap();
+                    return true;
+                }
+                // Soup isn't Send, and parsing is CPU bound anyway
+                let page = tokio::task::spawn_blocking(move || {
+                    parse_cinema_page(&cinema_html, cinema_id, &film_ids)
+                })
+                .await
+                .unwrap();
+                for (problem, count) in &page.problems {
+                    prog.println(format!(
+                        "{name}: skipped {problem} ({count}x), see `cip scrape --check`"
+                    ));
+                }
+                // films go first, the seances reference them
+                for film in page.discovered {
+                    prog.set_message(format!(
+                        "Downloading film missing from the list: {}",
+                        film.name
+                    ));
+                    sender
+                        .send(Scraped::Film(discover_film(film).await))
+                        .await
+                        .unwrap();
+                }
+                prog.disable_steady_tick();
+                prog.set_style(PROG_BAR_STYLE.clone());
+                prog.set_message(name);
+                prog.set_length(page.seances.len() as u64);
+                prog.finish();
+                sender.send(Scraped::Seances(page.seances)).await.unwrap();
+                true
+            }
+        }))
+        .buffer_unordered(args.concurrency.max(1))
+        .collect::<Vec<bool>>()
+        .await;
+    // the writer finishes once every sender is gone
+    drop(sender);
+    writer.await.unwrap();
+    let failed = downloaded.iter().filter(|downloaded| !**downloaded).count();
+    if failed > 0 {
+        println!("{failed} cinema pages couldn't be downloaded, their previous seances were kept");
+        std::process::exit(1);
+    }
+}
+
+/// The hashes of the pages the current programme was parsed from, to tell which are
+/// unchanged, which the cache can't as it's updated even when a scrape fails. None with
+/// --no-cache, so that every page is parsed again.
+fn page_hashes(db_path: &Path, no_cache: bool) -> HashMap<String, String> {
+    if no_cache {
+        return HashMap::new();
+    }
+    let db = Database::open(db_path);
+    db.conn().unwrap().get_page_hashes().unwrap()
+}
+
+/// Re-fetches some cinema pages and replaces their seances, or only those of some films,
+/// in the current programme. Partial scrapes aren't archived, as they don't see the
+/// whole programme.
+async fn scrape_partial(args: ScrapeArgs) {
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    let films = conn.get_films().unwrap();
+    if films.is_empty() {
+        println!("There's no programme to update yet, run a full `cip scrape` first");
+        std::process::exit(1);
+    }
+    let film_ids = args
+        .film
+        .iter()
+        .map(|pattern| {
+            let matching = films
+                .iter()
+                .filter(|film| film.matches(pattern))
+                .collect::<Vec<_>>();
+            match matching.as_slice() {
+                [film] => film.id,
+                [] => {
+                    println!("Film {pattern} not found");
+                    std::process::exit(2);
+                }
+                _ => {
+                    println!("Film {pattern} is ambiguous:");
+                    for film in matching {
+                        println!("  {} {}", style_id(film.id), film.description());
+                    }
+                    std::process::exit(2);
+                }
+            }
+        })
+        .collect::<HashSet<_>>();
+    let cinemas = if args.cinema.is_empty() {
+        let showing = conn
+            .query_seances(QueryOptions::default())
+            .unwrap()
+            .into_iter()
+            .filter(|r| film_ids.contains(&r.film.id))
+            .map(|r| r.cinema.id)
+            .collect::<HashSet<_>>();
+        conn.get_cinemas()
+            .unwrap()
+            .into_iter()
+            .filter(|cinema| showing.contains(&cinema.id))
+            .collect::<Vec<_>>()
+    } else {
+        args.cinema
+            .iter()
+            .map(|cinema| find_cinema(&conn, cinema))
+            .collect()
+    };
+    if cinemas.is_empty() {
+        println!("No cinema is showing these films, a full `cip scrape` would find new ones");
+        std::process::exit(1);
+    }
+
+    let known = Arc::new(
+        films
+            .iter()
+            .map(|film| (film.url_path.clone(), film.id))
+            .collect::<HashMap<_, _>>(),
+    );
+    let pages = futures::stream::iter(cinemas.iter().map(|cinema| {
+        let known = known.clone();
+        async move {
+            let html = reqwest::get(cinema.url())
+                .await
+                .and_then(|response| response.error_for_status())?
+                .text()
+                .await?;
+            let id = cinema.id;
+            let page = tokio::task::spawn_blocking(move || parse_cinema_page(&html, id, &known))
+                .await
+                .unwrap();
+            let mut discovered = Vec::new();
+            for film in page.discovered {
+                discovered.push(discover_film(film).await);
+            }
+            Ok::<_, reqwest::Error>((page.seances, discovered, page.problems))
+        }
+    }))
+    .buffered(args.concurrency.max(1))
+    .collect::<Vec<_>>()
+    .await;
+
+    let tx = conn.unchecked_transaction().unwrap();
+    let mut known = films.iter().map(|film| film.id).collect::<HashSet<_>>();
+    let mut id = conn.get_max_seance_id().unwrap();
+    let mut failed = false;
+    for (cinema, page) in cinemas.iter().zip(pages) {
+        // an error page isn't an empty programme
+        let (seances, discovered, problems) = match page {
+            Ok(page) => page,
+            Err(err) => {
+                println!("{}: {err}, kept its seances", cinema.description());
+                failed = true;
+                continue;
+            }
+        };
+        for (problem, count) in &problems {
+            println!(
+                "{}: skipped {problem} ({count}x), see `cip scrape --check`",
+                cinema.name
+            );
+        }
+        for film in discovered {
+            if known.insert(film.id) {
+                conn.insert_film(&film).unwrap();
+            }
+        }
+        let removed = conn.delete_seances(cinema.id, &film_ids).unwrap();
+        // only full scrapes record the pages the programme was parsed from
+        conn.delete_page_hash(cinema.url().as_str()).unwrap();
+        let mut seen = HashSet::new();
+        for mut seance in seances {
+            if !film_ids.is_empty() && !film_ids.contains(&seance.film_id) {
+                continue;
+            }
+            let key = (
+                seance.film_id,
+                seance.datetime,
+                seance.version.clone(),
+                seance.url.clone(),
+            );
+            if seen.insert(key) {
+                id += 1;
+                seance.id = id;
+                conn.insert_seance(&seance).unwrap();
+            }
+        }
+        println!(
+            "{}: replaced {removed} seances with {}",
+            cinema.description(),
+            seen.len()
+        );
+    }
+    tx.commit().unwrap();
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// What the cinema page tasks send to the writer
+#[derive(Debug)]
+enum Scraped {
+    Film(Film),
+    Seances(Vec<Seance>),
+    /// The hash of a cinema page, to tell whether it changed on the next scrape
+    Page {
+        url: String,
+        hash: String,
+    },
+    /// The cinema page hasn't changed since the current programme was scraped
+    Unchanged {
+        cinema_id: u64,
+        url_path: String,
+    },
+    /// The cinema page couldn't be downloaded, so its seances are kept as they are
+    Failed {
+        cinema_id: u64,
+        url_path: String,
+    },
+}
+
+/// An ID for a film missing from the film list, derived from its URL so it's the same
+/// on every scrape, and far above the IDs of the film list so they can't clash
+fn discovered_film_id(url_path: &str) -> u64 {
+    // short enough to type
+    1_000_000_000 + fnv1a(url_path.as_bytes()) % 1_000_000_000
+}
+
+/// 64-bit FNV-1a, a hash which stays the same across runs and versions of Rust
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn page_hash(body: &str) -> String {
+    format!("{:016x}", fnv1a(body.as_bytes()))
+}
+
+/// Fills in a film found on a cinema page from its own page, keeping what the cinema
+/// page told us when the film page can't be downloaded
+async fn discover_film(mut film: Film) -> Film {
+    let html = match reqwest::get(film.url()).await {
+        Ok(response) => response.error_for_status().ok(),
+        Err(_) => None,
+    };
+    let Some(html) = html else {
+        return film;
+    };
+    let Ok(html) = html.text().await else {
+        return film;
+    };
+    tokio::task::spawn_blocking(move || {
+        parse_film_page(&html, &mut film);
+        film
+    })
+    .await
+    .unwrap()
+}
+
+/// Reads the title, poster, director and release date of a film page, from its Open
+/// Graph tags and the markup the film list uses
+fn parse_film_page(html: &str, film: &mut Film) {
+    let soup = Soup::new(html);
+    let meta = |property: &str| {
+        soup.tag("meta")
+            .attr("property", property.to_string())
+            .find()
+            .and_then(|tag| tag.get("content"))
+            .map(|content| content.trim().to_string())
+            .filter(|content| !content.is_empty())
+    };
+    let text = |class: &str| {
+        soup.class(class.to_string())
+            .find()
+            .map(|tag| tag.text().trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+    if let Some(name) = meta("og:title").or_else(|| soup.tag("h1").find().map(|h1| h1.text())) {
+        film.name = name.trim().to_string();
+    }
+    if let Some(image) = meta("og:image") {
+        film.image_path = image;
+    }
+    if let Some(director) = text("director") {
+        film.director = director;
+    }
+    if let Some(release_date) = text("release-date") {
+        film.release_date = release_date;
+    }
+}
+
+/// What a cinema page lists
+struct CinemaPage {
+    /// Without IDs, which the writer assigns
+    seances: Vec<Seance>,
+    /// Films missing from the film list, with what the page says about them
+    discovered: Vec<Film>,
+    films: usize,
+    /// What couldn't be parsed, with how many times it happened
+    problems: BTreeMap<String, usize>,
+}
+
+/// Parses a cinema page, skipping and reporting the parts which don't have the
+/// expected layout
+fn parse_cinema_page(html: &str, cinema_id: u64, film_ids: &HashMap<String, u64>) -> CinemaPage {
+    let cinema_soup = Soup::new(html);
+    let mut page = CinemaPage {
+        seances: Vec::new(),
+        discovered: Vec::new(),
+        films: 0,
+        problems: BTreeMap::new(),
+    };
+    let mut problem = |problem: &str| *page.problems.entry(problem.to_string()).or_default() += 1;
+    fn text(soup: &impl QueryBuilderExt, class: &str) -> Option<String> {
+        soup.class(class.to_string())
+            .find()
+            .map(|tag| tag.text().trim().to_string())
+    }
+    for film_soup in cinema_soup.class("movie-results-container").find_all() {
+        let Some(poster) = film_soup.class("poster").find() else {
+            problem("film without a .poster");
+            continue;
+        };
+        let Some(url_path) = poster.get("href") else {
+            problem(".poster without a link");
+            continue;
+        };
+        page.films += 1;
+        let film_id = match film_ids.get(&url_path) {
+            Some(film_id) => *film_id,
+            None => {
+                let id = discovered_film_id(&url_path);
+                if !page.discovered.iter().any(|film| film.id == id) {
+                    let image = poster.tag("img").find();
+                    let slug = url_path.trim_end_matches('/').rsplit('/').next();
+                    page.discovered.push(Film {
+                        id,
+                        name: image
+                            .as_ref()
+                            .and_then(|img| img.get("alt"))
+                            .filter(|alt| !alt.trim().is_empty())
+                            .or_else(|| slug.map(|slug| slug.replace('-', " ")))
+                            .unwrap_or_default(),
+                        image_path: image.and_then(|img| img.get("src")).unwrap_or_default(),
+                        url_path: url_path.clone(),
+                        director: String::new(),
+                        release_date: String::new(),
+                        discovered: true,
+                    });
+                }
+                id
+            }
+        };
+        for seance_soup in film_soup.class("session-date").find_all() {
+            // e.g. "Lundi 19/10"
+            let Some(date) = text(&seance_soup, "sessionDate") else {
+                problem("session without a .sessionDate");
+                continue;
+            };
+            let Some(date) = date
+                .split_once(' ')
+                .and_then(|(_, date)| try_parse_date(date))
+            else {
+                problem(".sessionDate not in format \"Weekday DD/MM\"");
+                continue;
+            };
+            let Some(time) = text(&seance_soup, "time") else {
+                problem("session without a .time");
+                continue;
+            };
+            let Ok(time) = NaiveTime::parse_from_str(&time, "%H:%M") else {
+                problem(".time not in format HH:MM");
+                continue;
+            };
+            let Some(version) = text(&seance_soup, "version").filter(|v| !v.is_empty()) else {
+                problem("session without a .version");
+                continue;
+            };
+            let datetime = paris_time(NaiveDateTime::new(date, time));
+            let url = seance_soup
+                .tag("a")
+                .find()
+                .and_then(|link| link.get("href"));
+            page.seances.push(Seance {
+                id: 0,
+                cinema_id,
+                film_id,
+                datetime,
+                version,
+                url,
+            });
+        }
+    }
+    page
+}
+
+/// Replaces the programme with the cinemas, films and batches of seances received, then
+/// archives it, all in one transaction so readers keep seeing the previous programme
+/// until the new one is complete
+fn write_programme(
+    db_path: &Path,
+    cinemas: Vec<Cinema>,
+    films: Vec<Film>,
+    films_hash: String,
+    mut previous: HashMap<String, Vec<QueryResult>>,
+    mut receiver: mpsc::Receiver<Scraped>,
+    prog: ProgressBar,
+) {
+    // Collected before writing anything, so that the database isn't locked while pages
+    // are downloading, which writing each cinema's batch as it arrives would do. Holding
+    // the whole programme is cheap, a few thousand seances of a hundred bytes or so, and
+    // a scrape is all or nothing anyway as the programme is replaced in one transaction.
+    // Pages which fail to download keep their previous seances rather than failing it.
+    let mut film_ids = films.iter().map(|film| film.id).collect::<HashSet<_>>();
+    let mut discovered = Vec::new();
+    let mut seen = HashSet::new();
+    let mut seances = Vec::new();
+    let mut pages = vec![(FILMS_URL.to_string(), films_hash)];
+    while let Some(scraped) = receiver.blocking_recv() {
+        let batch = match scraped {
+            Scraped::Page { url, hash } => {
+                pages.push((url, hash));
+                continue;
+            }
+            Scraped::Film(film) => {
+                // several cinemas may list the same missing film
+                if film_ids.insert(film.id) {
+                    discovered.push(film);
+                }
+                continue;
+            }
+            Scraped::Seances(batch) => batch,
+            Scraped::Unchanged {
+                cinema_id,
+                url_path,
+            }
+            | Scraped::Failed {
+                cinema_id,
+                url_path,
+            } => {
+                let results = previous.remove(&url_path).unwrap_or_default();
+                let mut batch = Vec::new();
+                for QueryResult {
+                    film, mut seance, ..
+                } in results
+                {
+                    // films missing from the film list aren't in it on this scrape either
+                    if film_ids.insert(film.id) {
+                        discovered.push(film);
+                    }
+                    seance.cinema_id = cinema_id;
+                    batch.push(seance);
+                }
+                batch
+            }
+        };
+        for mut seance in batch {
+            let key = (
+                seance.cinema_id,
+                seance.film_id,
+                seance.datetime,
+                seance.version.clone(),
+                seance.url.clone(),
+            );
+            if seen.insert(key) {
+                seance.id = seances.len() as u64 + 1;
+                seances.push(seance);
+                prog.inc(1);
+            }
+        }
+    }
+
+    let db = Database::open(db_path);
+    let conn = db.conn().unwrap();
+    let tx = conn.unchecked_transaction().unwrap();
+    conn.clear_programme().unwrap();
+    for cinema in &cinemas {
+        conn.insert_cinema(cinema).unwrap();
+    }
+    for film in films.iter().chain(&discovered) {
+        conn.insert_film(film).unwrap();
+    }
+    for seance in &seances {
+        conn.insert_seance(seance).unwrap();
+    }
+    for (url, hash) in &pages {
+        conn.insert_page_hash(url, hash).unwrap();
+    }
+    conn.archive_programme(*NOW).unwrap();
+    tx.commit().unwrap();
+    prog.finish_with_message(format!(
+        "Inserted {} cinemas, {} films ({} missing from the film list) and {} seances",
+        cinemas.len(),
+        films.len() + discovered.len(),
+        discovered.len(),
+        seances.len()
+    ));
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum GroupBy {
+    Cinema,
+    Film,
+}
+
+#[derive(Args, Debug)]
+struct QueryArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Day to query DD/MM
+    #[arg(long, short = 'd')]
+    day: Option<String>,
+    /// Time to query after HH:MM
+    #[arg(long, short = 't')]
+    time: Option<String>,
+    /// Show VF only
+    #[arg(long)]
+    vf: bool,
+    /// Show VO only
+    #[arg(long)]
+    vo: bool,
+    /// Show favourite cinemas only
+    #[arg(long)]
+    fav: bool,
+    /// Group by cinemas or films
+    #[arg(long, short = 'g', value_enum)]
+    group: Option<GroupBy>,
+    /// Sort cinemas by distance to LAT,LON or a location saved in the config file
+    #[arg(long)]
+    near: Option<String>,
+    /// Only show cinemas within this distance of --near, e.g. 2km or 500m
+    #[arg(long, requires = "near")]
+    radius: Option<String>,
+    /// Apply a profile from the config file
+    #[arg(long, short = 'p')]
+    profile: Option<String>,
+    /// Don't apply the default profile
+    #[arg(long, conflicts_with = "profile")]
+    no_profile: bool,
+}
+
+type Grouping = BTreeMap<u64, (String, BTreeMap<u64, (String, Vec<QueryResult>)>)>;
+
+/// Returns a plain style when colour output is disabled
+fn styled(style: Style) -> Style {
+    if COLOR.load(Ordering::Relaxed) {
+        style
+    } else {
+        Style::new()
+    }
+}
+
+fn style_id(id: u64) -> ANSIGenericString<'static, str> {
+    styled(Style::new().dimmed()).paint(format!("[{id}]"))
+}
+
+async fn query(args: QueryArgs) {
+    let default_profile = Profile::default();
+    let profile = if args.no_profile {
+        &default_profile
+    } else {
+        CONFIG
+            .profile(args.profile.as_deref())
+            .unwrap_or(&default_profile)
+    };
     let options = QueryOptions {
         day: args.day.as_ref().map(|d| parse_date(d)),
-        time: args.time.as_ref().map(|t| parse_time(t)),
+        time: args
+            .time
+            .as_ref()
+            .or(profile.after.as_ref())
+            .map(|t| parse_time(t)),
         version: if args.vf && !args.vo {
             Some(Version::French)
         } else if !args.vf && args.vo {
             Some(Version::Original)
+        } else if !args.vf && !args.vo {
+            profile.version
         } else {
             None
         },
+        favourites: args.fav || profile.favourites,
+        ..Default::default()
     };
+    let group = args.group.or(profile.group).unwrap_or(CONFIG.group);
+    let near = args.near.as_ref().or(profile.near.as_ref()).map(|near| {
+        geo::Coordinates::resolve(near).unwrap_or_else(|err| {
+            println!("{err}");
+            std::process::exit(2);
+        })
+    });
+    let radius = args
+        .radius
+        .as_ref()
+        .or(profile.radius.as_ref())
+        .map(|radius| {
+            geo::parse_radius(radius).unwrap_or_else(|err| {
+                println!("{err}");
+                std::process::exit(2);
+            })
+        });
     let db = Database::open(&args.db_path);
     let conn = db.conn().unwrap();
+    let mut distances = HashMap::new();
+    if let Some(near) = near {
+        for cinema in conn.get_cinemas().unwrap() {
+            if let Some(location) = geo::locate(&conn, &cinema.address()) {
+                distances.insert(cinema.id, location.coordinates.distance(&near));
+            }
+        }
+    }
+    let cinema_description = |cinema: &Cinema| match distances.get(&cinema.id) {
+        Some(distance) => format!("{} · {distance:.1} km", cinema.description()),
+        None if near.is_some() => format!("{} · distance unknown", cinema.description()),
+        None => cinema.description(),
+    };
+    let whole_programme = options.day.is_none() && options.time.is_none();
     let mut grouping = Grouping::new();
     for result in conn.query_seances(options).unwrap() {
-        match args.group {
+        // cinemas which couldn't be located are only left out when there's a radius
+        if let Some(radius) = radius {
+            match distances.get(&result.cinema.id) {
+                Some(distance) if *distance <= radius => {}
+                _ => continue,
+            }
+        }
+        match group {
             GroupBy::Cinema => grouping
                 .entry(result.cinema.id)
-                .or_insert_with(|| (result.cinema.description(), BTreeMap::new()))
+                .or_insert_with(|| (cinema_description(&result.cinema), BTreeMap::new()))
                 .1
                 .entry(result.film.id)
                 .or_insert_with(|| (result.film.description(), Vec::new()))
@@ -625,16 +1989,35 @@ async fn query(args: QueryArgs) {
                 .or_insert_with(|| (result.film.description(), BTreeMap::new()))
                 .1
                 .entry(result.cinema.id)
-                .or_insert_with(|| (result.cinema.description(), Vec::new()))
+                .or_insert_with(|| (cinema_description(&result.cinema), Vec::new()))
                 .1
                 .push(result),
         }
     }
+    // cinemas which couldn't be located go last
+    let by_distance = |a: &u64, b: &u64| match (distances.get(a), distances.get(b)) {
+        (Some(a), Some(b)) => a.partial_cmp(b).unwrap(),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    };
+    let mut grouping = grouping
+        .into_iter()
+        .map(|(id, (description, group))| {
+            (id, (description, group.into_iter().collect::<Vec<_>>()))
+        })
+        .collect::<Vec<_>>();
+    if near.is_some() {
+        match group {
+            GroupBy::Cinema => grouping.sort_by(|(a, _), (b, _)| by_distance(a, b)),
+            GroupBy::Film => grouping
+                .iter_mut()
+                .for_each(|(_, (_, group))| group.sort_by(|(a, _), (b, _)| by_distance(a, b))),
+        }
+    }
     for (id, (description, group)) in grouping {
         println!(
             "{} {}\n",
             style_id(id),
-            Style::new().bold().paint(description)
+            styled(Style::new().bold()).paint(description)
         );
         for (id, (description, results)) in group {
             println!("  {} {}", style_id(id), description);
@@ -643,8 +2026,8 @@ async fn query(args: QueryArgs) {
                 print!(
                     " {} {} ({})",
                     style_id(result.seance.id),
-                    if args.day.is_none() && args.time.is_none() {
-                        result.seance.datetime.format("%d/%m %H:%M")
+                    if whole_programme {
+                        result.seance.datetime.format(&DATETIME_FORMAT)
                     } else {
                         result.seance.datetime.format("%H:%M")
                     },
@@ -661,8 +2044,11 @@ pub struct SeanceArgs {
     /// Seance ID
     id: u64,
     /// Database file path
-    #[arg(long, default_value = DEFAULT_DB_PATH.display().to_string())]
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
     db_path: PathBuf,
+    /// Show the booking link as a QR code
+    #[arg(long)]
+    qr: bool,
 }
 
 async fn seance(args: SeanceArgs) {
@@ -675,26 +2061,549 @@ async fn seance(args: SeanceArgs) {
         return;
     };
     println!("{}", style_id(result.seance.id),);
-    println!("Film:    {}", result.film.description());
-    println!("         {}", result.film.director);
-    println!("         {}", result.film.url());
-    println!("Cinema:  {}", result.cinema.name);
-    println!("         {}", result.cinema.address);
-    println!("         {}", result.cinema.url());
-    println!("Version: {}", result.seance.version);
-    println!("Date:    {}", result.seance.datetime.format("%b %d"));
-    println!("Time:    {}", result.seance.datetime.format("%H:%M"));
-    if let Some(url) = result.seance.url {
-        println!("Reserve: {url}");
+    for line in seance_details(&result) {
+        println!("{line}");
+    }
+    if args.qr {
+        let url = result
+            .seance
+            .url
+            .clone()
+            .unwrap_or_else(|| result.film.url().to_string());
+        println!("\n{}", qr_code(&url));
+    }
+
+    let film_seances = conn
+        .query_seances(QueryOptions {
+            film: Some(result.film.id),
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .filter(|r| r.seance.id != result.seance.id && r.seance.datetime >= *NOW)
+        .collect::<Vec<_>>();
+    if !film_seances.is_empty() {
+        println!("\n{}", styled(Style::new().bold()).paint("Other seances"));
+        for other in film_seances {
+            println!(
+                "  {} {} ({}) {}",
+                style_id(other.seance.id),
+                other.seance.datetime.format(&DATETIME_FORMAT),
+                other.seance.version,
+                other.cinema.description()
+            );
+        }
+    }
+
+    let cinema_seances = conn
+        .query_seances(QueryOptions {
+            day: Some(result.seance.day()),
+            time: Some(*EVENING_START),
+            cinema: Some(result.cinema.id),
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .filter(|r| r.film.id != result.film.id)
+        .collect::<Vec<_>>();
+    if !cinema_seances.is_empty() {
+        println!(
+            "\n{}",
+            styled(Style::new().bold()).paint(format!("Same evening at {}", result.cinema.name))
+        );
+        for other in cinema_seances {
+            println!(
+                "  {} {} ({}) {}",
+                style_id(other.seance.id),
+                other.seance.datetime.format("%H:%M"),
+                other.seance.version,
+                other.film.description()
+            );
+        }
+    }
+}
+
+fn seance_details(result: &QueryResult) -> Vec<String> {
+    let mut lines = vec![
+        format!("Film:    {}", result.film.description()),
+        format!("         {}", result.film.director),
+        format!("         {}", result.film.url()),
+        format!("Cinema:  {}", result.cinema.name),
+        format!("         {}", result.cinema.address),
+        format!("         {}", result.cinema.url()),
+        format!("Version: {}", result.seance.version),
+        format!("Date:    {}", result.seance.datetime.format("%b %d")),
+        format!("Time:    {}", result.seance.datetime.format("%H:%M")),
+    ];
+    if result.film.discovered {
+        lines.insert(3, "         Only listed on cinema pages".to_string());
+    }
+    if let Some(url) = &result.seance.url {
+        lines.push(format!("Reserve: {url}"));
+    }
+    lines
+}
+
+#[derive(Args, Debug)]
+struct OpenArgs {
+    /// Seance ID
+    id: u64,
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Open the film page
+    #[arg(long, group = "page")]
+    film: bool,
+    /// Open the cinema page
+    #[arg(long, group = "page")]
+    cinema: bool,
+    /// Open the booking page (default)
+    #[arg(long, group = "page")]
+    book: bool,
+    /// Print the URL instead of opening it
+    #[arg(long)]
+    print: bool,
+}
+
+async fn open(args: OpenArgs) {
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    let result = if let Some(result) = conn.get_seance(args.id).unwrap() {
+        result
+    } else {
+        println!("Seance {} not found", style_id(args.id));
+        std::process::exit(1);
+    };
+    let url = if args.film {
+        result.film.url().to_string()
+    } else if args.cinema {
+        result.cinema.url().to_string()
+    } else if let Some(url) = result.seance.url {
+        url
+    } else {
+        eprintln!("Seance has no booking link, using the film page");
+        result.film.url().to_string()
+    };
+    if args.print {
+        println!("{url}");
+    } else if let Err(err) = open_url(&url) {
+        println!("{err}");
+        std::process::exit(1);
+    }
+}
+
+/// Renders a QR code with Unicode half-blocks, light on dark to suit most terminals
+fn qr_code(data: &str) -> String {
+    use qrcode::render::unicode::Dense1x2;
+    qrcode::QrCode::new(data)
+        .unwrap()
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build()
+}
+
+/// Opens a URL or file with $BROWSER, falling back to xdg-open
+fn open_url(url: &str) -> std::io::Result<()> {
+    let opener = std::env::var("BROWSER").unwrap_or_else(|_| "xdg-open".to_string());
+    let status = std::process::Command::new(opener)
+        .arg(url)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!("Could not open {url}")))
+    }
+}
+
+#[derive(Args, Debug)]
+struct LastChanceArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Show films whose last seance is within the next N days. Films still showing on the
+    /// last day of the published programme are never shown.
+    #[arg(long, default_value_t = 7)]
+    days: i64,
+    /// Show films with fewer than K seances left
+    #[arg(long, short = 'k', default_value_t = 3)]
+    min_seances: usize,
+}
+
+async fn last_chance(args: LastChanceArgs) {
+    let options = QueryOptions::default();
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    let mut films = BTreeMap::<u64, Vec<QueryResult>>::new();
+    for result in conn.query_seances(options).unwrap() {
+        if result.seance.datetime < *NOW {
+            continue;
+        }
+        films.entry(result.film.id).or_default().push(result);
+    }
+    // the site only publishes about a week ahead, so films still showing on the last day
+    // it covers may well be programmed beyond it
+    let horizon = films
+        .values()
+        .map(|results| results.last().unwrap().seance.datetime.date_naive())
+        .max();
+    let cutoff = *NOW + chrono::Duration::days(args.days);
+    let mut films = films
+        .into_values()
+        .filter(|results| {
+            let last = results.last().unwrap().seance.datetime;
+            Some(last.date_naive()) != horizon
+                && (last <= cutoff || results.len() < args.min_seances)
+        })
+        .collect::<Vec<_>>();
+    films.sort_by_key(|results| results.last().unwrap().seance.datetime);
+    for results in films {
+        let first = &results[0];
+        let last = results.last().unwrap();
+        println!(
+            "{} {}",
+            style_id(first.film.id),
+            styled(Style::new().bold()).paint(first.film.description())
+        );
+        println!(
+            "  Last seance {} ({} left)\n",
+            last.seance.datetime.format(&DATETIME_FORMAT),
+            results.len()
+        );
+        let mut cinemas = BTreeMap::<u64, (String, Vec<&QueryResult>)>::new();
+        for result in &results {
+            cinemas
+                .entry(result.cinema.id)
+                .or_insert_with(|| (result.cinema.description(), Vec::new()))
+                .1
+                .push(result);
+        }
+        for (id, (description, results)) in cinemas {
+            println!("  {} {}", style_id(id), description);
+            print!("   ");
+            for result in results {
+                print!(
+                    " {} {} ({})",
+                    style_id(result.seance.id),
+                    result.seance.datetime.format(&DATETIME_FORMAT),
+                    result.seance.version
+                );
+            }
+            println!("\n");
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct NewArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Show films first programmed on or after DD/MM (defaults to last Wednesday)
+    #[arg(long, short = 's', value_parser = parse_past_date)]
+    since: Option<NaiveDate>,
+}
+
+fn last_wednesday() -> NaiveDate {
+    let today = NOW.date_naive();
+    today - chrono::Duration::days((today.weekday().num_days_from_monday() as i64 + 5) % 7)
+}
+
+async fn new(args: NewArgs) {
+    let options = QueryOptions::default();
+    let since = args.since.unwrap_or_else(last_wednesday);
+    let week_start = last_wednesday();
+    let week_end = week_start + chrono::Duration::days(7);
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    let mut films = BTreeMap::<u64, Vec<QueryResult>>::new();
+    for result in conn.query_seances(options).unwrap() {
+        films.entry(result.film.id).or_default().push(result);
+    }
+    // the programme only starts on the day of the scrape, so when films were first
+    // programmed is only known from scrapes archived before `since`
+    let first_seen = match conn.get_runs().unwrap().first() {
+        Some(run) if run.started_at.date_naive() < since => {
+            Some(conn.get_films_first_seen().unwrap())
+        }
+        _ => None,
+    };
+    let (mut releases, mut rereleases) = (Vec::new(), Vec::new());
+    for results in films.into_values() {
+        let first = &results[0];
+        let release = first.film.release();
+        let released_this_week = release.is_some_and(|r| r >= week_start && r < week_end);
+        let programmed_since = match &first_seen {
+            Some(first_seen) => first_seen
+                .get(&first.film.id)
+                .is_none_or(|first_seen| first_seen.date_naive() >= since),
+            None => release.is_some_and(|r| r >= since),
+        };
+        if !programmed_since && !released_this_week {
+            continue;
+        }
+        let is_rerelease = release.is_some_and(|r| r.year() < since.year() - 1);
+        if is_rerelease {
+            rereleases.push(results);
+        } else {
+            releases.push(results);
+        }
+    }
+    for (title, films) in [("New releases", releases), ("Re-releases", rereleases)] {
+        if films.is_empty() {
+            continue;
+        }
+        println!("{}\n", styled(Style::new().bold().underline()).paint(title));
+        for results in films {
+            let first = &results[0];
+            let mut cinemas = results.iter().map(|r| r.cinema.id).collect::<Vec<_>>();
+            cinemas.sort();
+            cinemas.dedup();
+            println!(
+                "{} {}",
+                style_id(first.film.id),
+                styled(Style::new().bold()).paint(first.film.description())
+            );
+            println!("  {}", first.film.director);
+            println!(
+                "  From {}, {} seances in {} cinemas\n",
+                first.seance.datetime.format(&DATETIME_FORMAT),
+                results.len(),
+                cinemas.len()
+            );
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct WatchArgs {
+    /// Database file path
+    #[arg(long, global = true, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    #[command(subcommand)]
+    command: WatchCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum WatchCommands {
+    /// Watch a film title, director or film ID
+    Add {
+        /// Title, director or film ID to watch for
+        pattern: String,
+        /// What the pattern should be matched against
+        #[arg(long, short = 'k', value_enum, default_value_t = WatchKind::Title)]
+        kind: WatchKind,
+    },
+    /// Stop watching
+    Remove {
+        /// Watch ID
+        id: u64,
+    },
+    /// List watches
+    List,
+    /// Report newly programmed seances matching the watchlist, exiting with 1 if there are any
+    Check,
+}
+
+async fn watch(args: WatchArgs) {
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    match args.command {
+        WatchCommands::Add { pattern, kind } => {
+            if matches!(kind, WatchKind::Film) && pattern.parse::<u64>().is_err() {
+                println!("Film ID should be a number");
+                std::process::exit(2);
+            }
+            conn.insert_watch(kind, pattern.trim()).unwrap();
+        }
+        WatchCommands::Remove { id } => {
+            if conn.delete_watch(id).unwrap() == 0 {
+                println!("Watch {} not found", style_id(id));
+            }
+        }
+        WatchCommands::List => {
+            for watch in conn.get_watches().unwrap() {
+                println!("{} {}", style_id(watch.id), watch.description());
+            }
+        }
+        WatchCommands::Check => {
+            let watches = conn.get_watches().unwrap();
+            let results = conn.query_seances(QueryOptions::default()).unwrap();
+            let mut found = false;
+            for watch in &watches {
+                let mut header = false;
+                for result in results.iter().filter(|r| r.seance.datetime >= *NOW) {
+                    if !watch.matches(&result.film) || !conn.mark_watch_seen(watch, result).unwrap()
+                    {
+                        continue;
+                    }
+                    if !header {
+                        println!(
+                            "{} {}",
+                            style_id(watch.id),
+                            styled(Style::new().bold()).paint(watch.description())
+                        );
+                        header = true;
+                    }
+                    println!(
+                        "  {} {} ({}) {} at {}",
+                        style_id(result.seance.id),
+                        result.seance.datetime.format(&DATETIME_FORMAT),
+                        result.seance.version,
+                        result.film.description(),
+                        result.cinema.description()
+                    );
+                }
+                if header {
+                    println!();
+                    found = true;
+                }
+            }
+            if found {
+                std::process::exit(1);
+            }
+        }
     }
 }
 
-async fn clean(args: ScrapeArgs) {
+#[derive(Args, Debug)]
+struct FavArgs {
+    /// Database file path
+    #[arg(long, global = true, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    #[command(subcommand)]
+    command: FavCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum FavCommands {
+    /// Add a cinema to the favourites
+    Add {
+        /// Cinema ID or name
+        cinema: String,
+    },
+    /// Remove a cinema from the favourites
+    Remove {
+        /// Cinema ID or name
+        cinema: String,
+    },
+    /// List favourite cinemas
+    List,
+}
+
+fn find_cinema(conn: &Connection, cinema: &str) -> Cinema {
+    let mut cinemas = conn.get_cinemas().unwrap();
+    if let Ok(id) = cinema.parse::<u64>() {
+        cinemas.retain(|c| c.id == id);
+    } else {
+        let name = fold(cinema);
+        if let Some(exact) = cinemas.iter().position(|c| fold(&c.name) == name) {
+            cinemas = vec![cinemas.swap_remove(exact)];
+        } else {
+            cinemas.retain(|c| fold(&c.name).contains(&name));
+        }
+    }
+    match cinemas.len() {
+        1 => cinemas.pop().unwrap(),
+        0 => {
+            println!("Cinema {cinema} not found");
+            std::process::exit(2);
+        }
+        _ => {
+            println!("Cinema {cinema} is ambiguous:");
+            for cinema in cinemas {
+                println!("  {} {}", style_id(cinema.id), cinema.description());
+            }
+            std::process::exit(2);
+        }
+    }
+}
+
+async fn fav(args: FavArgs) {
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    match args.command {
+        FavCommands::Add { cinema } => {
+            conn.insert_favourite(&find_cinema(&conn, &cinema)).unwrap();
+        }
+        FavCommands::Remove { cinema } => {
+            conn.delete_favourite(&find_cinema(&conn, &cinema)).unwrap();
+        }
+        FavCommands::List => {
+            for cinema in conn.get_favourites().unwrap() {
+                println!("{} {}", style_id(cinema.id), cinema.description());
+            }
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Show the configuration, including environment variable overrides
+    Show,
+    /// Print the config file path
+    Path,
+    /// Open the config file in $VISUAL or $EDITOR
+    Edit,
+}
+
+async fn config(args: ConfigArgs) {
+    match args.command {
+        ConfigCommands::Show => print!("{}", toml::to_string_pretty(&*CONFIG).unwrap()),
+        ConfigCommands::Path => println!("{}", CONFIG_PATH.display()),
+        ConfigCommands::Edit => {
+            if !CONFIG_PATH.exists() {
+                std::fs::create_dir_all(CONFIG_PATH.parent().unwrap()).unwrap();
+                let config = toml::to_string_pretty(&Config::default()).unwrap();
+                std::fs::write(&*CONFIG_PATH, config).unwrap();
+            }
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| "vi".to_string());
+            let status = std::process::Command::new(editor)
+                .arg(&*CONFIG_PATH)
+                .status()
+                .unwrap();
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
+            let config = std::fs::read_to_string(&*CONFIG_PATH).unwrap();
+            let config = toml::from_str::<Config>(&config)
+                .map_err(|err| format!("Invalid config file {}: {err}", CONFIG_PATH.display()))
+                .and_then(|config| config.validate());
+            if let Err(err) = config {
+                println!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct CleanArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+}
+
+async fn clean(args: CleanArgs) {
     Database::delete(args.db_path);
 }
 
 #[derive(Parser, Debug)]
 struct Cli {
+    /// Disable colour output
+    #[arg(long, global = true)]
+    no_color: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -707,17 +2616,112 @@ enum Commands {
     Query(QueryArgs),
     /// Get information about a seance
     Seance(SeanceArgs),
+    /// Open the booking, film or cinema page of a seance
+    Open(OpenArgs),
+    /// List films that are about to disappear from the programme
+    LastChance(LastChanceArgs),
+    /// List newly programmed films
+    New(NewArgs),
+    /// Manage the watchlist
+    Watch(WatchArgs),
+    /// Manage favourite cinemas
+    Fav(FavArgs),
+    /// Keep track of the seances you plan to go to
+    Agenda(agenda::AgendaArgs),
+    /// Estimate whether there's time to get from one seance to another
+    Route(geo::RouteArgs),
+    /// Plan a day of back to back seances
+    Plan(plan::PlanArgs),
+    /// Find the seances that suit a group of people best
+    Together(together::TogetherArgs),
+    /// Show statistics about the programme
+    Stats(stats::StatsArgs),
+    /// Show the seances added, cancelled or moved since a previous scrape
+    Diff(diff::DiffArgs),
+    /// Explore the archive of every seance seen by past scrapes
+    History(history::HistoryArgs),
+    /// Geocode cinema addresses
+    Geo(geo::GeoArgs),
+    /// Browse the programme in an interactive terminal UI
+    Tui(tui::TuiArgs),
+    /// Manage the database schema
+    Db(db::DbArgs),
+    /// Keep the database fresh by scraping on a schedule, and send notifications
+    Daemon(daemon::DaemonArgs),
+    /// Show or edit the configuration
+    Config(ConfigArgs),
     /// Delete database
-    Clean(ScrapeArgs),
+    Clean(CleanArgs),
 }
 
 #[tokio::main]
 async fn main() {
     let args: Cli = Cli::parse();
+    if let Some(err) = &CONFIG.error {
+        let editing = matches!(
+            &args.command,
+            Commands::Config(ConfigArgs {
+                command: ConfigCommands::Edit
+            })
+        );
+        if !editing {
+            println!("{err}");
+            std::process::exit(2);
+        }
+    }
+    COLOR.store(CONFIG.color && !args.no_color, Ordering::Relaxed);
     match args.command {
         Commands::Scrape(args) => scrape(args).await,
         Commands::Query(args) => query(args).await,
         Commands::Seance(args) => seance(args).await,
+        Commands::Open(args) => open(args).await,
+        Commands::LastChance(args) => last_chance(args).await,
+        Commands::New(args) => new(args).await,
+        Commands::Watch(args) => watch(args).await,
+        Commands::Fav(args) => fav(args).await,
+        Commands::Agenda(args) => agenda::agenda(args).await,
+        Commands::Route(args) => geo::route(args).await,
+        Commands::Plan(args) => plan::plan(args).await,
+        Commands::Together(args) => together::together(args).await,
+        Commands::Stats(args) => stats::stats(args).await,
+        Commands::Diff(args) => diff::diff(args).await,
+        Commands::History(args) => history::history(args).await,
+        Commands::Geo(args) => geo::geo(args).await,
+        Commands::Tui(args) => tui::tui(args).await,
+        Commands::Db(args) => db::db(args).await,
+        Commands::Daemon(args) => daemon::daemon(args).await,
+        Commands::Config(args) => config(args).await,
         Commands::Clean(args) => clean(args).await,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_cache_ignores_page_hashes() {
+        let path = std::env::temp_dir().join(format!("cip-page-hashes-{}.db", std::process::id()));
+        let db = Database::open(&path);
+        db.conn()
+            .unwrap()
+            .insert_page_hash("https://www.cip-paris.fr/cinema/champo", "0123456789abcdef")
+            .unwrap();
+        assert_eq!(page_hashes(&path, false).len(), 1);
+        assert!(page_hashes(&path, true).is_empty());
+        Database::delete(&path);
+    }
+
+    #[test]
+    fn paris_time_follows_summer_time() {
+        let at = |date: &str| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap();
+        let offset = |date| paris_time(at(date)).offset().local_minus_utc() / 3600;
+        assert_eq!(offset("2026-03-29 01:59"), 1);
+        assert_eq!(offset("2026-03-29 03:00"), 2);
+        assert_eq!(offset("2026-10-24 20:00"), 2);
+        // the hour clocks go back is the earlier one
+        assert_eq!(offset("2026-10-25 02:30"), 2);
+        assert_eq!(offset("2026-10-25 03:00"), 1);
+        assert_eq!(offset("2026-12-31 20:00"), 1);
+    }
+}
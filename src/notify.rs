@@ -0,0 +1,200 @@
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Webhooks, emails and the notify command taking longer than this count as failed
+pub const TIMEOUT_SECONDS: u64 = 30;
+
+const DEFAULT_PAYLOAD: &str = r#"{"event": "{event}", "title": "{title}", "body": "{body}"}"#;
+
+/// What a notification is about, which sinks can be limited to
+#[derive(clap::ValueEnum, Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// A scheduled scrape finished
+    ScrapeCompleted,
+    /// A scheduled scrape failed, panicked or timed out
+    ScrapeFailed,
+    /// New seances match the watchlist
+    Watchlist,
+    /// A seance on the agenda was moved, changed or cancelled
+    Agenda,
+    /// Seances were added, cancelled or moved since the previous scrape
+    Programme,
+}
+
+impl Event {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::ScrapeCompleted => "scrape_completed",
+            Self::ScrapeFailed => "scrape_failed",
+            Self::Watchlist => "watchlist",
+            Self::Agenda => "agenda",
+            Self::Programme => "programme",
+        }
+    }
+}
+
+pub struct Notification {
+    pub event: Event,
+    pub title: String,
+    pub body: String,
+}
+
+/// Whether a sink limited to `events` wants a notification, no events meaning all of them
+pub fn wanted(events: &[Event], notification: &Notification) -> bool {
+    events.is_empty() || events.contains(&notification.event)
+}
+
+/// POSTs every notification as JSON
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    url: String,
+    /// JSON in which {event}, {title} and {body} are replaced by the notification's,
+    /// defaults to an object with these three fields
+    payload: Option<String>,
+    /// Only send these events, defaults to all of them
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// Upgrade the connection with STARTTLS, on port 587 by default
+    #[default]
+    Starttls,
+    /// Connect over TLS, on port 465 by default
+    Tls,
+    /// Send in the clear, on port 25 by default, e.g. to a local mail catcher
+    None,
+}
+
+/// Emails every notification through an SMTP server
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Email {
+    server: String,
+    port: Option<u16>,
+    #[serde(default)]
+    security: Security,
+    username: Option<String>,
+    /// Left out of `cip config show`
+    #[serde(skip_serializing)]
+    password: Option<String>,
+    from: String,
+    to: Vec<String>,
+    /// Only send these events, defaults to all of them
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+/// Fills in a payload template. The values are JSON escaped, as they're meant to go in
+/// JSON strings.
+fn render(template: &str, notification: &Notification) -> String {
+    let escape = |value: &str| {
+        let quoted = serde_json::to_string(value).unwrap();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let fields = [
+        ("{event}", escape(notification.event.as_str())),
+        ("{title}", escape(&notification.title)),
+        ("{body}", escape(&notification.body)),
+    ];
+    // in a single pass, so that a title containing {body} stays as it is
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some((index, placeholder, value)) = fields
+        .iter()
+        .filter_map(|(placeholder, value)| {
+            rest.find(placeholder)
+                .map(|index| (index, placeholder, value))
+        })
+        .min_by_key(|(index, _, _)| *index)
+    {
+        rendered.push_str(&rest[..index]);
+        rendered.push_str(value);
+        rest = &rest[index + placeholder.len()..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+impl Webhook {
+    pub async fn send(&self, notification: &Notification) -> Result<(), String> {
+        if !wanted(&self.events, notification) {
+            return Ok(());
+        }
+        let payload = render(
+            self.payload.as_deref().unwrap_or(DEFAULT_PAYLOAD),
+            notification,
+        );
+        if let Err(err) = serde_json::from_str::<serde_json::Value>(&payload) {
+            return Err(format!("the payload isn't valid JSON: {err}"));
+        }
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(TIMEOUT_SECONDS))
+            .build()
+            .map_err(|err| err.to_string())?
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+}
+
+impl Email {
+    pub async fn send(&self, notification: &Notification) -> Result<(), String> {
+        if !wanted(&self.events, notification) {
+            return Ok(());
+        }
+        let mailbox = |address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|err| format!("invalid address {address}: {err}"))
+        };
+        let mut message = Message::builder()
+            .from(mailbox(&self.from)?)
+            .subject(format!("cip: {}", notification.title));
+        for to in &self.to {
+            message = message.to(mailbox(to)?);
+        }
+        let message = message
+            .body(notification.body.clone())
+            .map_err(|err| err.to_string())?;
+
+        let mut transport = match self.security {
+            Security::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.server)
+                    .map_err(|err| err.to_string())?
+            }
+            Security::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.server)
+                .map_err(|err| err.to_string())?,
+            Security::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.server).port(25)
+            }
+        };
+        if let Some(port) = self.port {
+            transport = transport.port(port);
+        }
+        transport = transport.timeout(Some(Duration::from_secs(TIMEOUT_SECONDS)));
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        transport
+            .build()
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+}
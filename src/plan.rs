@@ -0,0 +1,457 @@
+use crate::{
+    fold,
+    geo::{locate, Location, Station, Travel},
+    parse_date, style_id, styled, Database, QueryOptions, QueryResult, CONFIG, NOW,
+};
+use ansi_term::Style;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Weekday};
+use clap::Args;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    path::PathBuf,
+};
+
+/// Travel time assumed between cinemas which couldn't be located
+const UNKNOWN_TRAVEL: f64 = 30.;
+
+#[derive(Args, Debug)]
+pub struct PlanArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Day to plan: today, tomorrow, a weekday or DD/MM
+    #[arg(long, short = 'd', default_value = "today")]
+    day: String,
+    /// Comma separated film titles or IDs to see, defaults to the watchlist
+    #[arg(long, short = 'f', value_delimiter = ',')]
+    films: Vec<String>,
+    /// Number of watchlist films to fit in when --films isn't given
+    #[arg(long, short = 'n', default_value_t = 3)]
+    count: usize,
+    /// Longest wait between two seances, e.g. 90m or 1h30
+    #[arg(long, value_parser = parse_minutes)]
+    max_gap: Option<i64>,
+    /// Stay in the same cinema
+    #[arg(long)]
+    same_cinema: bool,
+    /// Length of each seance in minutes, trailers included
+    #[arg(long, default_value_t = 120)]
+    runtime: i64,
+    /// Number of itineraries to show
+    #[arg(long, default_value_t = 5)]
+    limit: usize,
+}
+
+/// Parses a duration such as "90m", "1h30", "2h" or "45" (minutes)
+pub fn parse_minutes(duration: &str) -> Result<i64, String> {
+    let err = || format!("Duration should be in format 90m or 1h30: {duration}");
+    let duration = duration.trim().to_lowercase();
+    let (hours, minutes) = match duration.split_once('h') {
+        Some((hours, minutes)) => (hours.parse::<i64>().map_err(|_| err())?, minutes),
+        None => (0, duration.as_str()),
+    };
+    let minutes = minutes.trim_end_matches("min").trim_end_matches('m');
+    let minutes = if minutes.is_empty() {
+        0
+    } else {
+        minutes.parse::<i64>().map_err(|_| err())?
+    };
+    Ok(hours * 60 + minutes)
+}
+
+/// Parses a weekday in English or French
+pub fn parse_weekday(day: &str) -> Option<Weekday> {
+    Some(match fold(day.trim()).as_str() {
+        "monday" | "mon" | "lundi" => Weekday::Mon,
+        "tuesday" | "tue" | "mardi" => Weekday::Tue,
+        "wednesday" | "wed" | "mercredi" => Weekday::Wed,
+        "thursday" | "thu" | "jeudi" => Weekday::Thu,
+        "friday" | "fri" | "vendredi" => Weekday::Fri,
+        "saturday" | "sat" | "samedi" => Weekday::Sat,
+        "sunday" | "sun" | "dimanche" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+/// Parses today, tomorrow, a weekday in English or French, or DD/MM
+fn parse_day(day: &str) -> Result<NaiveDate, String> {
+    let today = NOW.date_naive();
+    let weekday = match fold(day).as_str() {
+        "today" | "aujourd'hui" => return Ok(today),
+        "tomorrow" | "demain" => return Ok(today.succ_opt().unwrap()),
+        _ if day.contains('/') => return Ok(parse_date(day)),
+        _ => parse_weekday(day).ok_or_else(|| format!("Unknown day {day}"))?,
+    };
+    let days_ahead =
+        (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+    Ok(today + chrono::Duration::days(days_ahead as i64))
+}
+
+struct Itinerary<'a> {
+    seances: Vec<(&'a QueryResult, f64)>,
+    waiting: i64,
+    rank: Rank,
+}
+
+/// Most films first, then least waiting, then finishing earliest
+type Rank = (Reverse<usize>, i64, DateTime<FixedOffset>);
+
+impl PartialEq for Itinerary<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank == other.rank
+    }
+}
+
+impl Eq for Itinerary<'_> {}
+
+impl PartialOrd for Itinerary<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Itinerary<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank.cmp(&other.rank)
+    }
+}
+
+struct Planner<'a> {
+    /// Seances in time order with the wanted film they count for
+    candidates: Vec<(&'a QueryResult, usize)>,
+    travel: HashMap<(u64, u64), f64>,
+    runtime: i64,
+    max_gap: Option<i64>,
+    same_cinema: bool,
+    /// Stop extending itineraries which have this many films
+    target: usize,
+    /// Only keep itineraries with `target` films
+    complete_only: bool,
+    limit: usize,
+    /// The best `limit` itineraries so far, the worst of them on top
+    itineraries: BinaryHeap<Itinerary<'a>>,
+}
+
+impl<'a> Planner<'a> {
+    fn travel(&self, from: &QueryResult, to: &QueryResult) -> f64 {
+        if from.cinema.id == to.cinema.id {
+            0.
+        } else {
+            self.travel[&(from.cinema.id, to.cinema.id)]
+        }
+    }
+
+    /// Adds the itinerary to the best ones if it's better than the worst of them
+    fn keep(&mut self, path: &[(usize, f64)]) {
+        let waiting = path
+            .windows(2)
+            .map(|pair| {
+                let (prev, next) = (self.candidates[pair[0].0].0, self.candidates[pair[1].0].0);
+                (next.seance.datetime - prev.seance.datetime).num_minutes() - self.runtime
+            })
+            .sum();
+        let last = self.candidates[path.last().unwrap().0].0;
+        let rank = (Reverse(path.len()), waiting, last.seance.datetime);
+        if self.itineraries.len() >= self.limit
+            && self
+                .itineraries
+                .peek()
+                .is_none_or(|worst| rank >= worst.rank)
+        {
+            return;
+        }
+        self.itineraries.push(Itinerary {
+            seances: path
+                .iter()
+                .map(|(idx, travel)| (self.candidates[*idx].0, *travel))
+                .collect(),
+            waiting,
+            rank,
+        });
+        if self.itineraries.len() > self.limit {
+            self.itineraries.pop();
+        }
+    }
+
+    /// Depth first search over the seances after `start`
+    fn search(&mut self, start: usize, path: &mut Vec<(usize, f64)>, covered: &mut Vec<bool>) {
+        if !path.is_empty() && (!self.complete_only || path.len() == self.target) {
+            self.keep(path);
+        }
+        if path.len() == self.target {
+            return;
+        }
+        for next in start..self.candidates.len() {
+            let (result, wanted) = self.candidates[next];
+            if covered[wanted] {
+                continue;
+            }
+            let travel = match path.last() {
+                Some((prev, _)) => {
+                    let prev = self.candidates[*prev].0;
+                    if self.same_cinema && prev.cinema.id != result.cinema.id {
+                        continue;
+                    }
+                    let gap = (result.seance.datetime - prev.seance.datetime).num_minutes()
+                        - self.runtime;
+                    let travel = self.travel(prev, result);
+                    if (gap as f64) < travel || self.max_gap.is_some_and(|max| gap > max) {
+                        continue;
+                    }
+                    travel
+                }
+                None => 0.,
+            };
+            covered[wanted] = true;
+            path.push((next, travel));
+            self.search(next + 1, path, covered);
+            path.pop();
+            covered[wanted] = false;
+        }
+    }
+}
+
+/// Minutes between every pair of cinemas, by their IDs
+fn travel_times(
+    stations: &[Station],
+    locations: &HashMap<u64, Option<Location>>,
+) -> HashMap<(u64, u64), f64> {
+    let mut travel = HashMap::new();
+    for (a, from) in locations {
+        for (b, to) in locations {
+            let minutes = match (from, to) {
+                (Some(from), Some(to)) => {
+                    Travel::estimate(stations, &from.coordinates, &to.coordinates).minutes()
+                }
+                _ => UNKNOWN_TRAVEL,
+            };
+            travel.insert((*a, *b), minutes);
+        }
+    }
+    travel
+}
+
+pub async fn plan(args: PlanArgs) {
+    let day = parse_day(&args.day).unwrap_or_else(|err| {
+        println!("{err}");
+        std::process::exit(2);
+    });
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    let results = conn
+        .query_seances(QueryOptions {
+            day: Some(day),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let mut candidates = Vec::new();
+    let (wanted_count, target);
+    if args.films.is_empty() {
+        let watches = conn.get_watches().unwrap();
+        if watches.is_empty() {
+            println!("The watchlist is empty, add films with `cip watch add` or use --films");
+            std::process::exit(2);
+        }
+        let mut films = Vec::new();
+        for result in &results {
+            if watches.iter().any(|watch| watch.matches(&result.film)) {
+                let wanted = match films.iter().position(|id| *id == result.film.id) {
+                    Some(wanted) => wanted,
+                    None => {
+                        films.push(result.film.id);
+                        films.len() - 1
+                    }
+                };
+                candidates.push((result, wanted));
+            }
+        }
+        wanted_count = films.len();
+        target = args.count.min(wanted_count);
+    } else {
+        for (wanted, film) in args.films.iter().enumerate() {
+            let before = candidates.len();
+            candidates.extend(
+                results
+                    .iter()
+                    .filter(|r| r.film.matches(film))
+                    .map(|r| (r, wanted)),
+            );
+            if candidates.len() == before {
+                println!(
+                    "No seances of {} on {}",
+                    args.films[wanted],
+                    day.format("%d/%m")
+                );
+                std::process::exit(1);
+            }
+        }
+        candidates.sort_by_key(|(r, _)| r.seance.datetime);
+        wanted_count = args.films.len();
+        target = wanted_count;
+    }
+
+    let stations = conn.get_stations().unwrap();
+    let mut locations = HashMap::new();
+    for (result, _) in &candidates {
+        locations
+            .entry(result.cinema.id)
+            .or_insert_with(|| locate(&conn, &result.cinema.address()));
+    }
+    let travel = travel_times(&stations, &locations);
+
+    let mut planner = Planner {
+        candidates,
+        travel,
+        runtime: args.runtime,
+        max_gap: args.max_gap,
+        same_cinema: args.same_cinema,
+        target,
+        complete_only: !args.films.is_empty(),
+        limit: args.limit,
+        itineraries: BinaryHeap::new(),
+    };
+    planner.search(0, &mut Vec::new(), &mut vec![false; wanted_count]);
+    let itineraries = planner.itineraries.into_sorted_vec();
+    if itineraries.is_empty() {
+        println!("No itinerary found on {}", day.format("%d/%m"));
+        std::process::exit(1);
+    }
+
+    for (rank, itinerary) in itineraries.iter().enumerate() {
+        let (first, last) = (itinerary.seances[0].0, itinerary.seances.last().unwrap().0);
+        let end = last.seance.datetime + chrono::Duration::minutes(args.runtime);
+        println!(
+            "{}",
+            styled(Style::new().bold()).paint(format!(
+                "#{} {} film{}, {} min waiting, {} to ~{}",
+                rank + 1,
+                itinerary.seances.len(),
+                if itinerary.seances.len() == 1 {
+                    ""
+                } else {
+                    "s"
+                },
+                itinerary.waiting,
+                first.seance.datetime.format("%H:%M"),
+                end.format("%H:%M")
+            ))
+        );
+        for (idx, (result, travel)) in itinerary.seances.iter().enumerate() {
+            if idx > 0 && *travel > 0. {
+                println!("        ~{travel:.0} min to {}", result.cinema.name);
+            }
+            println!(
+                "  {} {} ({}) {} at {}",
+                style_id(result.seance.id),
+                result.seance.datetime.format("%H:%M"),
+                result.seance.version,
+                result.film.name,
+                result.cinema.description()
+            );
+        }
+        println!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cinema, Film, Seance};
+
+    fn seance(id: u64, cinema_id: u64, film_id: u64, time: &str) -> QueryResult {
+        QueryResult {
+            cinema: Cinema {
+                id: cinema_id,
+                name: format!("Cinema {cinema_id}"),
+                url_path: format!("/cinema/{cinema_id}"),
+                address: String::new(),
+                image_path: String::new(),
+            },
+            film: Film {
+                id: film_id,
+                name: format!("Film {film_id}"),
+                url_path: format!("/film/{film_id}"),
+                image_path: String::new(),
+                director: String::new(),
+                release_date: String::new(),
+                discovered: false,
+            },
+            seance: Seance {
+                id,
+                cinema_id,
+                film_id,
+                datetime: DateTime::parse_from_rfc3339(&format!("2026-10-19T{time}:00+02:00"))
+                    .unwrap(),
+                version: "VO".to_string(),
+                url: None,
+            },
+        }
+    }
+
+    /// The seance IDs of the best itineraries seeing every film, best first, with
+    /// 2 hour seances in cinemas none of which could be located
+    fn plan(seances: &[QueryResult], limit: usize) -> Vec<Vec<u64>> {
+        let mut films = seances.iter().map(|r| r.film.id).collect::<Vec<_>>();
+        films.sort();
+        films.dedup();
+        let locations = seances.iter().map(|r| (r.cinema.id, None)).collect();
+        let mut planner = Planner {
+            candidates: seances
+                .iter()
+                .map(|r| (r, films.iter().position(|id| *id == r.film.id).unwrap()))
+                .collect(),
+            travel: travel_times(&[], &locations),
+            runtime: 120,
+            max_gap: None,
+            same_cinema: false,
+            target: films.len(),
+            complete_only: true,
+            limit,
+            itineraries: BinaryHeap::new(),
+        };
+        planner.search(0, &mut Vec::new(), &mut vec![false; films.len()]);
+        planner
+            .itineraries
+            .into_sorted_vec()
+            .iter()
+            .map(|itinerary| itinerary.seances.iter().map(|(r, _)| r.seance.id).collect())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_best_itineraries() {
+        let seances = [
+            seance(1, 1, 10, "14:00"),
+            seance(2, 1, 10, "16:00"),
+            seance(3, 1, 20, "18:00"),
+            seance(4, 1, 20, "20:00"),
+        ];
+        // 1 then 3 is found first, and dropped for 2 then 3 which has no wait
+        assert_eq!(plan(&seances, 1), [[2, 3]]);
+        // with as much waiting, finishing earlier is better
+        assert_eq!(plan(&seances, 2), [[2, 3], [1, 3]]);
+    }
+
+    #[test]
+    fn leaves_time_to_travel() {
+        assert_eq!(
+            travel_times(&[], &[(1, None), (2, None)].into_iter().collect())[&(1, 2)],
+            UNKNOWN_TRAVEL
+        );
+        let too_soon = [seance(1, 1, 10, "14:00"), seance(2, 2, 20, "16:20")];
+        assert!(plan(&too_soon, 5).is_empty());
+        let in_time = [seance(1, 1, 10, "14:00"), seance(2, 2, 20, "16:30")];
+        assert_eq!(plan(&in_time, 5), [[1, 2]]);
+        // no travel within a cinema
+        let same_cinema = [seance(1, 1, 10, "14:00"), seance(2, 1, 20, "16:00")];
+        assert_eq!(plan(&same_cinema, 5), [[1, 2]]);
+    }
+
+    #[test]
+    fn finds_nothing_when_seances_overlap() {
+        let seances = [seance(1, 1, 10, "14:00"), seance(2, 1, 20, "15:00")];
+        assert!(plan(&seances, 5).is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{style_id, styled, Database, QueryOptions, CONFIG};
+use ansi_term::Style;
+use chrono::{NaiveDate, Timelike};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Number of films and time slots to list
+    #[arg(long, default_value_t = 10)]
+    limit: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct CinemaStats {
+    id: u64,
+    name: String,
+    seances: usize,
+    vo: usize,
+    vf: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct FilmStats {
+    id: u64,
+    name: String,
+    seances: usize,
+    cinemas: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct DayStats {
+    day: NaiveDate,
+    seances: usize,
+    /// Seances starting in each hour of the day
+    hours: [usize; 24],
+}
+
+#[derive(Serialize, Debug)]
+struct Stats {
+    seances: usize,
+    /// Busiest first
+    cinemas: Vec<CinemaStats>,
+    /// Most widely screened first
+    films: Vec<FilmStats>,
+    days: Vec<DayStats>,
+    /// Seances starting in each hour over the whole programme
+    hours: [usize; 24],
+}
+
+impl Stats {
+    fn collect(db: &Database) -> Self {
+        let conn = db.conn().unwrap();
+        let results = conn.query_seances(QueryOptions::default()).unwrap();
+        let mut cinemas = BTreeMap::<u64, CinemaStats>::new();
+        let mut films = BTreeMap::<u64, (FilmStats, BTreeSet<u64>)>::new();
+        let mut days = BTreeMap::<NaiveDate, DayStats>::new();
+        let mut hours = [0; 24];
+        for result in &results {
+            let cinema = cinemas
+                .entry(result.cinema.id)
+                .or_insert_with(|| CinemaStats {
+                    id: result.cinema.id,
+                    name: result.cinema.description(),
+                    seances: 0,
+                    vo: 0,
+                    vf: 0,
+                });
+            cinema.seances += 1;
+            match result.seance.version.as_str() {
+                "VO" => cinema.vo += 1,
+                "VF" => cinema.vf += 1,
+                _ => {}
+            }
+            let (film, film_cinemas) = films.entry(result.film.id).or_insert_with(|| {
+                (
+                    FilmStats {
+                        id: result.film.id,
+                        name: result.film.description(),
+                        seances: 0,
+                        cinemas: 0,
+                    },
+                    BTreeSet::new(),
+                )
+            });
+            film.seances += 1;
+            film_cinemas.insert(result.cinema.id);
+            film.cinemas = film_cinemas.len();
+            let day = days.entry(result.seance.day()).or_insert_with(|| DayStats {
+                day: result.seance.day(),
+                seances: 0,
+                hours: [0; 24],
+            });
+            let hour = result.seance.datetime.hour() as usize;
+            day.seances += 1;
+            day.hours[hour] += 1;
+            hours[hour] += 1;
+        }
+        let mut cinemas = cinemas.into_values().collect::<Vec<_>>();
+        cinemas.sort_by_key(|c| std::cmp::Reverse(c.seances));
+        let mut films = films.into_values().map(|(f, _)| f).collect::<Vec<_>>();
+        films.sort_by_key(|f| std::cmp::Reverse((f.cinemas, f.seances)));
+        Self {
+            seances: results.len(),
+            cinemas,
+            films,
+            days: days.into_values().collect(),
+            hours,
+        }
+    }
+}
+
+/// Scales a count to a block character, for bars and heatmaps
+fn shade(count: usize, max: usize) -> char {
+    const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+    if count == 0 || max == 0 {
+        SHADES[0]
+    } else {
+        SHADES[1 + (count * (SHADES.len() - 1) - 1) / max]
+    }
+}
+
+fn bar(count: usize, max: usize, width: usize) -> String {
+    "█".repeat((count * width).div_ceil(max.max(1)))
+}
+
+fn print_text(stats: &Stats, limit: usize) {
+    let heading = |title: &str| println!("{}\n", styled(Style::new().bold()).paint(title));
+    println!("{} seances\n", stats.seances);
+
+    heading("Seances per cinema");
+    let max = stats.cinemas.first().map_or(0, |c| c.seances);
+    for cinema in &stats.cinemas {
+        let versions = (cinema.vo + cinema.vf).max(1);
+        println!(
+            "  {} {:<40} {:>5}  VO {:>3}% / VF {:>3}%  {}",
+            style_id(cinema.id),
+            cinema.name,
+            cinema.seances,
+            cinema.vo * 100 / versions,
+            cinema.vf * 100 / versions,
+            bar(cinema.seances, max, 20)
+        );
+    }
+    println!();
+
+    heading("Most widely screened films");
+    for film in stats.films.iter().take(limit) {
+        println!(
+            "  {} {:<50} {:>3} cinemas {:>5} seances",
+            style_id(film.id),
+            film.name,
+            film.cinemas,
+            film.seances
+        );
+    }
+    println!();
+
+    heading("Seances per day");
+    let max = stats.days.iter().map(|d| d.seances).max().unwrap_or(0);
+    for day in &stats.days {
+        println!(
+            "  {} {:>5}  {}",
+            day.day.format("%a %d/%m"),
+            day.seances,
+            bar(day.seances, max, 40)
+        );
+    }
+    println!();
+
+    heading("Busiest time slots");
+    let mut hours = stats.hours.iter().enumerate().collect::<Vec<_>>();
+    hours.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+    for (hour, count) in hours.into_iter().take(limit).filter(|(_, c)| **c > 0) {
+        println!("  {hour:02}:00-{:02}:00 {count:>5}", (hour + 1) % 24);
+    }
+    println!();
+
+    heading("Heatmap");
+    let active = (0..24).filter(|h| stats.hours[*h] > 0).collect::<Vec<_>>();
+    let (Some(first), Some(last)) = (active.first(), active.last()) else {
+        return;
+    };
+    print!("            ");
+    for hour in *first..=*last {
+        print!("{hour:<3}");
+    }
+    println!();
+    let max = stats.days.iter().flat_map(|d| d.hours).max().unwrap_or(0);
+    for day in &stats.days {
+        print!("  {}", day.day.format("%a %d/%m"));
+        for hour in *first..=*last {
+            let shade = shade(day.hours[hour], max);
+            print!(" {shade}{shade}");
+        }
+        println!();
+    }
+}
+
+pub async fn stats(args: StatsArgs) {
+    let db = Database::open(&args.db_path);
+    let stats = Stats::collect(&db);
+    match args.format {
+        Format::Text => print_text(&stats, args.limit),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&stats).unwrap()),
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    parse_time, plan::parse_weekday, style_id, styled, Database, QueryOptions, QueryResult,
+    Version, CONFIG, DATETIME_FORMAT, NOW,
+};
+use ansi_term::Style;
+use chrono::{Datelike, NaiveTime, Timelike};
+use clap::Args;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct TogetherArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+    /// Comma separated film titles or IDs to choose from
+    #[arg(long, short = 'f', required = true, value_delimiter = ',')]
+    film: Vec<String>,
+    /// Comma separated person files
+    #[arg(long, short = 'p', required = true, value_delimiter = ',')]
+    people: Vec<PathBuf>,
+    /// Length of a seance in minutes, trailers included
+    #[arg(long, default_value_t = 120)]
+    runtime: i64,
+    /// Number of seances to show
+    #[arg(long, default_value_t = 10)]
+    limit: usize,
+}
+
+/// When someone is free, e.g. `{ days = ["sat", "sun"], from = "14:00", to = "23:30" }`
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Window {
+    /// Weekdays the window applies to, every day if empty
+    #[serde(default)]
+    days: Vec<String>,
+    from: String,
+    to: String,
+}
+
+/// A person file, e.g. alice.toml
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Person {
+    /// Defaults to the file name
+    name: Option<String>,
+    /// Available at any time if empty
+    #[serde(default)]
+    availability: Vec<Window>,
+    /// Any arrondissement if empty
+    #[serde(default)]
+    arrondissements: Vec<u32>,
+    version: Option<Version>,
+}
+
+impl Person {
+    fn load(path: &Path) -> Self {
+        let person = std::fs::read_to_string(path).unwrap_or_else(|err| {
+            println!("Could not read {}: {err}", path.display());
+            std::process::exit(2);
+        });
+        let mut person: Person = toml::from_str(&person).unwrap_or_else(|err| {
+            println!("Invalid person file {}: {err}", path.display());
+            std::process::exit(2);
+        });
+        if person.name.is_none() {
+            person.name = path.file_stem().map(|s| s.to_string_lossy().to_string());
+        }
+        for window in &person.availability {
+            for day in &window.days {
+                if parse_weekday(day).is_none() {
+                    println!("Unknown day {day} in {}", path.display());
+                    std::process::exit(2);
+                }
+            }
+            for time in [&window.from, &window.to] {
+                if NaiveTime::parse_from_str(time, "%H:%M").is_err() {
+                    println!("Unknown time {time} in {}, expected HH:MM", path.display());
+                    std::process::exit(2);
+                }
+            }
+        }
+        person
+    }
+
+    fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or_default()
+    }
+
+    /// Whether the whole seance fits in one of the availability windows
+    fn is_available(&self, result: &QueryResult, runtime: i64) -> bool {
+        let start = result.seance.datetime;
+        let minutes = |time: NaiveTime| time.hour() as i64 * 60 + time.minute() as i64;
+        self.availability.is_empty()
+            || self.availability.iter().any(|window| {
+                let on_day = window.days.is_empty()
+                    || window
+                        .days
+                        .iter()
+                        .any(|day| parse_weekday(day) == Some(start.weekday()));
+                let (from, to) = (
+                    minutes(parse_time(&window.from)),
+                    minutes(parse_time(&window.to)),
+                );
+                let start = minutes(start.time());
+                // windows ending at or after midnight, e.g. 20:00 to 00:30
+                let to = if to <= from { to + 24 * 60 } else { to };
+                on_day && start >= from && start + runtime <= to
+            })
+    }
+
+    fn likes_arrondissement(&self, result: &QueryResult) -> bool {
+        self.arrondissements.is_empty()
+            || result
+                .cinema
+                .address()
+                .arrondissement()
+                .is_some_and(|a| self.arrondissements.contains(&a))
+    }
+
+    fn likes_version(&self, result: &QueryResult) -> bool {
+        self.version
+            .is_none_or(|version| version.short() == result.seance.version)
+    }
+}
+
+/// How well a seance suits the group
+struct Fit<'a> {
+    result: &'a QueryResult,
+    available: usize,
+    score: usize,
+    complaints: Vec<String>,
+}
+
+pub async fn together(args: TogetherArgs) {
+    let people = args
+        .people
+        .iter()
+        .map(|path| Person::load(path))
+        .collect::<Vec<_>>();
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    let results = conn
+        .query_seances(QueryOptions::default())
+        .unwrap()
+        .into_iter()
+        .filter(|r| r.seance.datetime >= *NOW && args.film.iter().any(|f| r.film.matches(f)))
+        .collect::<Vec<_>>();
+    if results.is_empty() {
+        println!("No upcoming seances of {}", args.film.join(", "));
+        std::process::exit(1);
+    }
+
+    let mut fits = results
+        .iter()
+        .map(|result| {
+            let mut fit = Fit {
+                result,
+                available: 0,
+                score: 0,
+                complaints: Vec::new(),
+            };
+            for person in &people {
+                let mut complaints = Vec::new();
+                if person.is_available(result, args.runtime) {
+                    fit.available += 1;
+                    fit.score += 2;
+                } else {
+                    complaints.push("not available");
+                }
+                if person.likes_arrondissement(result) {
+                    fit.score += 1;
+                } else {
+                    complaints.push("too far");
+                }
+                if person.likes_version(result) {
+                    fit.score += 1;
+                } else {
+                    complaints.push("wrong version");
+                }
+                if !complaints.is_empty() {
+                    fit.complaints
+                        .push(format!("{}: {}", person.name(), complaints.join(", ")));
+                }
+            }
+            fit
+        })
+        .collect::<Vec<_>>();
+    fits.sort_by_key(|fit| {
+        (
+            std::cmp::Reverse(fit.available),
+            std::cmp::Reverse(fit.score),
+            fit.result.seance.datetime,
+        )
+    });
+
+    for fit in fits.iter().take(args.limit) {
+        let result = fit.result;
+        println!(
+            "{} {} ({}) {} at {}",
+            style_id(result.seance.id),
+            result.seance.datetime.format(&DATETIME_FORMAT),
+            result.seance.version,
+            styled(Style::new().bold()).paint(&result.film.name),
+            result.cinema.description()
+        );
+        println!(
+            "  {}/{} available, score {}/{}",
+            fit.available,
+            people.len(),
+            fit.score,
+            people.len() * 4
+        );
+        for complaint in &fit.complaints {
+            println!("  {complaint}");
+        }
+        println!();
+    }
+}
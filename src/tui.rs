@@ -0,0 +1,489 @@
+use crate::{
+    fold, open_url, seance_details, Database, GroupBy, QueryOptions, QueryResult, Version, CONFIG,
+    NOW, PROJECT_DIRS,
+};
+use chrono::{NaiveDate, Utc};
+use clap::Args;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Tabs, Wrap},
+};
+use std::{collections::BTreeMap, io, path::PathBuf};
+
+#[derive(Args, Debug)]
+pub struct TuiArgs {
+    /// Database file path
+    #[arg(long, default_value = CONFIG.db_path.display().to_string())]
+    db_path: PathBuf,
+}
+
+enum Row {
+    Group(String),
+    Subgroup(String),
+    Seance(usize),
+}
+
+struct App {
+    results: Vec<QueryResult>,
+    days: Vec<NaiveDate>,
+    day: usize,
+    group: GroupBy,
+    version: Option<Version>,
+    filter: String,
+    editing: bool,
+    rows: Vec<Row>,
+    list: ListState,
+    status: String,
+}
+
+impl App {
+    fn new(results: Vec<QueryResult>) -> Self {
+        let mut days = results.iter().map(|r| r.seance.day()).collect::<Vec<_>>();
+        days.dedup();
+        let today = NOW.date_naive();
+        let day = days.iter().position(|d| *d >= today).unwrap_or(0);
+        let mut app = Self {
+            results,
+            days,
+            day,
+            group: CONFIG.group,
+            version: None,
+            filter: String::new(),
+            editing: false,
+            rows: Vec::new(),
+            list: ListState::default(),
+            status: String::new(),
+        };
+        app.refresh();
+        app
+    }
+
+    fn matches(&self, result: &QueryResult) -> bool {
+        if self.days.get(self.day) != Some(&result.seance.day()) {
+            return false;
+        }
+        if let Some(version) = self.version {
+            if result.seance.version != version.short() {
+                return false;
+            }
+        }
+        let filter = fold(&self.filter);
+        filter.is_empty()
+            || fold(&result.film.name).contains(&filter)
+            || fold(&result.cinema.name).contains(&filter)
+            || fold(&result.seance.version) == filter
+    }
+
+    /// Rebuilds the rows after the day, grouping or filters changed
+    fn refresh(&mut self) {
+        type Grouping = BTreeMap<u64, (String, BTreeMap<u64, (String, Vec<usize>)>)>;
+        let mut grouping = Grouping::new();
+        for (idx, result) in self.results.iter().enumerate() {
+            if !self.matches(result) {
+                continue;
+            }
+            let ((outer_id, outer), (inner_id, inner)) = match self.group {
+                GroupBy::Cinema => (
+                    (result.cinema.id, result.cinema.description()),
+                    (result.film.id, result.film.description()),
+                ),
+                GroupBy::Film => (
+                    (result.film.id, result.film.description()),
+                    (result.cinema.id, result.cinema.description()),
+                ),
+            };
+            grouping
+                .entry(outer_id)
+                .or_insert_with(|| (outer, BTreeMap::new()))
+                .1
+                .entry(inner_id)
+                .or_insert_with(|| (inner, Vec::new()))
+                .1
+                .push(idx);
+        }
+        self.rows.clear();
+        for (id, (description, group)) in grouping {
+            self.rows.push(Row::Group(format!("[{id}] {description}")));
+            for (id, (description, seances)) in group {
+                self.rows
+                    .push(Row::Subgroup(format!("  [{id}] {description}")));
+                self.rows.extend(seances.into_iter().map(Row::Seance));
+            }
+        }
+        let first = self.rows.iter().position(|r| matches!(r, Row::Seance(_)));
+        self.list.select(first);
+        *self.list.offset_mut() = 0;
+    }
+
+    fn selected(&self) -> Option<&QueryResult> {
+        match self.rows.get(self.list.selected()?)? {
+            Row::Seance(idx) => Some(&self.results[*idx]),
+            _ => None,
+        }
+    }
+
+    fn move_selection(&mut self, forward: bool) {
+        let Some(current) = self.list.selected() else {
+            return;
+        };
+        let is_seance = |idx: &usize| matches!(self.rows[*idx], Row::Seance(_));
+        let next = if forward {
+            (current + 1..self.rows.len()).find(is_seance)
+        } else {
+            (0..current).rev().find(is_seance)
+        };
+        if let Some(next) = next {
+            self.list.select(Some(next));
+        } else if !forward {
+            // keep the group headers of the first seance visible
+            *self.list.offset_mut() = 0;
+        }
+    }
+
+    fn open_booking(&mut self) {
+        let Some(result) = self.selected() else {
+            return;
+        };
+        let url = result
+            .seance
+            .url
+            .clone()
+            .unwrap_or_else(|| result.film.url().to_string());
+        self.status = match open_url(&url) {
+            Ok(()) => format!("Opened {url}"),
+            Err(err) => err.to_string(),
+        };
+    }
+
+    fn export_calendar(&mut self) {
+        let Some(result) = self.selected() else {
+            return;
+        };
+        let path = PROJECT_DIRS
+            .cache_dir()
+            .join(format!("seance-{}.ics", result.seance.id));
+        let export = std::fs::create_dir_all(PROJECT_DIRS.cache_dir())
+            .and_then(|()| std::fs::write(&path, ics(result)))
+            .and_then(|()| open_url(&path.display().to_string()));
+        self.status = match export {
+            Ok(()) => format!("Exported {}", path.display()),
+            Err(err) => format!("Could not export {}: {err}", path.display()),
+        };
+    }
+
+    /// Handles a key press, returning false when the app should quit
+    fn handle_key(&mut self, key: KeyCode) -> bool {
+        if self.editing {
+            match key {
+                KeyCode::Enter | KeyCode::Esc => self.editing = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.refresh();
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.refresh();
+                }
+                _ => {}
+            }
+            return true;
+        }
+        self.status.clear();
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Left | KeyCode::Char('h') if self.day > 0 => {
+                self.day -= 1;
+                self.refresh();
+            }
+            KeyCode::Right | KeyCode::Char('l') if self.day + 1 < self.days.len() => {
+                self.day += 1;
+                self.refresh();
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(true),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(false),
+            KeyCode::Char('g') => {
+                self.group = match self.group {
+                    GroupBy::Cinema => GroupBy::Film,
+                    GroupBy::Film => GroupBy::Cinema,
+                };
+                self.refresh();
+            }
+            KeyCode::Char('v') => {
+                self.version = match self.version {
+                    None => Some(Version::Original),
+                    Some(Version::Original) => Some(Version::French),
+                    Some(Version::French) => None,
+                };
+                self.refresh();
+            }
+            KeyCode::Char('/') => self.editing = true,
+            KeyCode::Char('o') => self.open_booking(),
+            KeyCode::Char('c') => self.export_calendar(),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = *Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
+            .split(frame.size())
+        else {
+            unreachable!()
+        };
+        let [list, details] = *Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+            .split(body)
+        else {
+            unreachable!()
+        };
+
+        let days = self
+            .days
+            .iter()
+            .map(|day| day.format("%a %d/%m").to_string())
+            .collect();
+        frame.render_widget(
+            Tabs::new(days)
+                .select(self.day)
+                .block(Block::default().borders(Borders::ALL).title("Day"))
+                .highlight_style(
+                    Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED),
+                ),
+            header,
+        );
+
+        let items = self
+            .rows
+            .iter()
+            .map(|row| match row {
+                Row::Group(title) => ListItem::new(title.as_str()).style(Style::default().bold()),
+                Row::Subgroup(title) => ListItem::new(title.as_str()),
+                Row::Seance(idx) => {
+                    let result = &self.results[*idx];
+                    let title = match self.group {
+                        GroupBy::Cinema => &result.film.name,
+                        GroupBy::Film => &result.cinema.name,
+                    };
+                    ListItem::new(Line::from(vec![
+                        Span::raw("    "),
+                        Span::styled(format!("[{}]", result.seance.id), Style::default().dim()),
+                        Span::raw(format!(
+                            " {} ({})",
+                            result.seance.datetime.format("%H:%M"),
+                            result.seance.version
+                        )),
+                        Span::styled(format!("  {title}"), Style::default().dim()),
+                    ]))
+                }
+            })
+            .collect::<Vec<_>>();
+        let title = format!(
+            "Seances by {} · {} · filter: {}{}",
+            match self.group {
+                GroupBy::Cinema => "cinema",
+                GroupBy::Film => "film",
+            },
+            self.version.map_or("VO+VF", |v| v.short()),
+            self.filter,
+            if self.editing { "_" } else { "" }
+        );
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            list,
+            &mut self.list,
+        );
+
+        let lines = self
+            .selected()
+            .map(|result| {
+                std::iter::once(format!("[{}]", result.seance.id))
+                    .chain(seance_details(result))
+                    .map(Line::from)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        frame.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title("Seance")),
+            details,
+        );
+
+        let help = if self.status.is_empty() {
+            "←/→ day  ↑/↓ seance  g group  v version  / filter  o open  c calendar  q quit"
+        } else {
+            &self.status
+        };
+        frame.render_widget(Paragraph::new(help).style(Style::default().dim()), footer);
+    }
+}
+
+/// Escapes an iCalendar TEXT value (RFC 5545 3.3.11)
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Folds a content line into lines of at most 75 octets, continued by a space (RFC 5545
+/// 3.1), without splitting characters
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+/// An iCalendar event for a seance, assuming a two hour screening
+fn ics(result: &QueryResult) -> String {
+    let format = "%Y%m%dT%H%M%SZ";
+    let start = result.seance.datetime.with_timezone(&Utc);
+    let end = start + chrono::Duration::hours(2);
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//cip//EN".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!(
+            "UID:seance-{}-{}@cip",
+            result.seance.id,
+            start.format(format)
+        ),
+        format!("DTSTAMP:{}", Utc::now().format(format)),
+        format!("DTSTART:{}", start.format(format)),
+        format!("DTEND:{}", end.format(format)),
+        format!(
+            "SUMMARY:{}",
+            escape_text(&format!("{} ({})", result.film.name, result.seance.version))
+        ),
+        format!(
+            "LOCATION:{}",
+            escape_text(&format!(
+                "{}, {}",
+                result.cinema.name, result.cinema.address
+            ))
+        ),
+    ];
+    if let Some(url) = &result.seance.url {
+        lines.push(format!("URL:{url}"));
+    }
+    lines.extend(["END:VEVENT".to_string(), "END:VCALENDAR".to_string()]);
+    lines
+        .iter()
+        .map(|line| fold_line(line))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+        + "\r\n"
+}
+
+pub async fn tui(args: TuiArgs) {
+    let db = Database::open(&args.db_path);
+    let conn = db.conn().unwrap();
+    let mut app = App::new(conn.query_seances(QueryOptions::default()).unwrap());
+
+    enable_raw_mode().unwrap();
+    execute!(io::stdout(), EnterAlternateScreen).unwrap();
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout())).unwrap();
+    let result = (|| -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| app.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+    disable_raw_mode().unwrap();
+    execute!(io::stdout(), LeaveAlternateScreen).unwrap();
+    result.unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cinema, Film, Seance};
+    use chrono::DateTime;
+
+    #[test]
+    fn ics_escapes_and_folds_text() {
+        let result = QueryResult {
+            cinema: Cinema {
+                id: 1,
+                name: "Le Champo; Espace Jacques Tati".to_string(),
+                url_path: "/cinema/champo".to_string(),
+                address: "51 rue des Écoles 75005 Paris".to_string(),
+                image_path: String::new(),
+            },
+            film: Film {
+                id: 10,
+                name: "Paris, je t'aime \\ Les Amants du Pont-Neuf et autres histoires".to_string(),
+                url_path: "/film/paris-je-t-aime".to_string(),
+                image_path: String::new(),
+                director: String::new(),
+                release_date: String::new(),
+                discovered: false,
+            },
+            seance: Seance {
+                id: 1,
+                cinema_id: 1,
+                film_id: 10,
+                datetime: DateTime::parse_from_rfc3339("2026-10-19T20:00:00+02:00").unwrap(),
+                version: "VO".to_string(),
+                url: None,
+            },
+        };
+        let ics = ics(&result);
+        assert!(ics.contains(
+            "SUMMARY:Paris\\, je t'aime \\\\ Les Amants du Pont-Neuf et autres histoires (V\r\n O)\r\n"
+        ));
+        assert!(ics.contains(
+            "LOCATION:Le Champo\\; Espace Jacques Tati\\, 51 rue des Écoles 75005 Paris\r\n"
+        ));
+        for line in ics.split("\r\n") {
+            assert!(line.len() <= 75, "{line}");
+        }
+    }
+
+    #[test]
+    fn folding_keeps_characters_whole() {
+        let line = format!("SUMMARY:{}", "é".repeat(40));
+        let folded = fold_line(&line);
+        assert_eq!(folded.split("\r\n ").collect::<String>(), line);
+        for part in folded.split("\r\n") {
+            assert!(part.len() <= 75);
+        }
+        assert_eq!(escape_text("a\nb"), "a\\nb");
+    }
+}